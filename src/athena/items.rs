@@ -1,9 +1,14 @@
-use std::collections::HashMap;
-use std::fs;
+use crate::error;
+use crate::error::Error;
+use log::warn;
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+
+pub const DEFAULT_ITEMS_PATH: &str = "./valid_items.json";
 
-#[derive(Hash, PartialEq, Eq, Deserialize, Serialize)]
-enum ItemType {
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ItemType {
     Character,
     Backpack,
     Pickaxe,
@@ -18,29 +23,120 @@ enum ItemType {
     MiscItem,
 }
 
-#[derive(Deserialize)]
-struct ActiveItems {
-    items: HashMap<ItemType, Vec<String>>,
+impl ItemType {
+    pub const ALL: [ItemType; 12] = [
+        ItemType::Character,
+        ItemType::Backpack,
+        ItemType::Pickaxe,
+        ItemType::Glider,
+        ItemType::SkyDiveContrail,
+        ItemType::Dance,
+        ItemType::ItemWrap,
+        ItemType::BannerIcon,
+        ItemType::BannerColor,
+        ItemType::MusicPack,
+        ItemType::LoadingScreen,
+        ItemType::MiscItem,
+    ];
 }
 
-struct ItemManager {
-    active_items: ActiveItems,
+/// The items of one [ItemType] that differ between two catalogues, see [ItemManager::diff].
+pub struct ItemDiff {
+    pub(crate) item_type: ItemType,
+    pub(crate) added: Vec<String>,
+    pub(crate) removed: Vec<String>,
+}
+
+pub struct ItemManager {
+    path: String,
+    active_items: HashMap<ItemType, Vec<String>>,
+    /// Maps the lowercase form of every item ID to its type.
+    item_types: HashMap<String, ItemType>,
 }
 
 impl ItemManager {
-    fn new() -> Result<Self, Box<dyn std::error::Error>> {
-        let file = fs::read_to_string("./active_items.json")?;
-        let active_items = serde_json::from_str(&file)?;
-        Ok(ItemManager { active_items })
-    }
-
-    fn get_item_type(&self, item_id: &str) -> Option<&ItemType> {
-        self.active_items.items.iter().find_map(|(item_type, ids)| {
-            if ids.contains(&item_id.to_string()) {
-                Some(item_type)
-            } else {
-                None
+    /// Loads the item catalogue from the path in `ITEMS_PATH`, or [DEFAULT_ITEMS_PATH] if it isn't
+    /// set.
+    pub fn new() -> error::Result<Self> {
+        let path = std::env::var("ITEMS_PATH").unwrap_or_else(|_| DEFAULT_ITEMS_PATH.to_string());
+        ItemManager::load(&path)
+    }
+
+    /// Loads the item catalogue from the supplied path. The file is an object of [ItemType] names to
+    /// arrays of item IDs, and types that have no items can be left out.
+    pub fn load(path: &str) -> error::Result<Self> {
+        let file = fs::read_to_string(path).map_err(|e| Error::ItemFileRead(path.to_string(), e))?;
+        let listed: HashMap<ItemType, Vec<String>> = serde_json::from_str(&file)
+            .map_err(|e| Error::MalformedItemFile(path.to_string(), e))?;
+        ItemManager::from_listed(path, listed)
+    }
+
+    /// Loads a fresh copy of the catalogue from the file this one was loaded from.
+    pub fn reload(&self) -> error::Result<Self> {
+        ItemManager::load(&self.path)
+    }
+
+    /// Builds the lookup index, dropping repeated IDs with a warning. An ID listed under two
+    /// different types is ambiguous and fails the whole catalogue.
+    pub(crate) fn from_listed(path: &str, listed: HashMap<ItemType, Vec<String>>) -> error::Result<Self> {
+        let mut active_items = HashMap::new();
+        let mut item_types = HashMap::new();
+
+        for (item_type, ids) in listed {
+            let mut unique = Vec::with_capacity(ids.len());
+            for id in ids {
+                match item_types.get(&id.to_lowercase()) {
+                    Some(existing) if *existing == item_type => {
+                        warn!("Item {} is listed more than once under {:?}", id, item_type);
+                    }
+                    Some(existing) => {
+                        return Err(Error::ConflictingItemType(id, *existing, item_type));
+                    }
+                    None => {
+                        item_types.insert(id.to_lowercase(), item_type);
+                        unique.push(id);
+                    }
+                }
             }
-        })
+            active_items.insert(item_type, unique);
+        }
+
+        Ok(ItemManager { path: path.to_string(), active_items, item_types })
+    }
+
+    /// Returns the type of the supplied item ID, ignoring case.
+    pub fn get_item_type(&self, item_id: &str) -> Option<&ItemType> {
+        self.item_types.get(&item_id.to_lowercase())
+    }
+
+    /// Returns every item of the supplied type, or an empty slice if there are none.
+    pub fn items_of(&self, item_type: &ItemType) -> &[String] {
+        self.active_items.get(item_type).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Returns every item that can be granted to a player's locker, which is everything except
+    /// [ItemType::MiscItem].
+    pub fn locker_items(&self) -> impl Iterator<Item = &String> {
+        self.active_items.iter()
+            .filter(|(item_type, _)| **item_type != ItemType::MiscItem)
+            .flat_map(|(_, ids)| ids)
+    }
+
+    /// Returns the items added and removed in `newer` compared to this catalogue, leaving out
+    /// types that didn't change.
+    pub fn diff(&self, newer: &ItemManager) -> Vec<ItemDiff> {
+        ItemType::ALL.iter()
+            .map(|item_type| {
+                // IDs are compared ignoring case, the same as [ItemManager::get_item_type].
+                let old = self.items_of(item_type).iter().map(|id| id.to_lowercase()).collect::<HashSet<_>>();
+                let new = newer.items_of(item_type).iter().map(|id| id.to_lowercase()).collect::<HashSet<_>>();
+                ItemDiff {
+                    item_type: *item_type,
+                    added: newer.items_of(item_type).iter().filter(|id| !old.contains(&id.to_lowercase())).cloned().collect(),
+                    removed: self.items_of(item_type).iter().filter(|id| !new.contains(&id.to_lowercase())).cloned().collect(),
+                }
+            })
+            .filter(|diff| !diff.added.is_empty() || !diff.removed.is_empty())
+            .collect()
     }
 }
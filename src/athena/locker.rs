@@ -0,0 +1,211 @@
+use crate::athena::items::ItemType;
+use crate::epic::epic_error::{make_epic_err, EpicError};
+use crate::mcp::commands::{parse_body, CommandOutput};
+use crate::mcp::profile::Profile;
+use crate::GlyphState;
+use axum::http::StatusCode;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+/// The profile item that holds the player's loadout for clients that use `SetCosmeticLockerSlot`.
+pub const LOCKER_ITEM_ID: &str = "glyph-loadout";
+pub const LOCKER_TEMPLATE_ID: &str = "CosmeticLocker:cosmeticlocker_athena";
+
+/// Every slot that can be changed from the locker.
+pub const LOCKER_SLOTS: [ItemType; 9] = [
+    ItemType::Character,
+    ItemType::Backpack,
+    ItemType::Pickaxe,
+    ItemType::Glider,
+    ItemType::SkyDiveContrail,
+    ItemType::Dance,
+    ItemType::ItemWrap,
+    ItemType::MusicPack,
+    ItemType::LoadingScreen,
+];
+
+/// Returns the athena stat that stores the item equipped in the supplied slot, or [None] if
+/// items of that type can't be equipped.
+pub fn favorite_stat(slot: &ItemType) -> Option<&'static str> {
+    match slot {
+        ItemType::Character => Some("favorite_character"),
+        ItemType::Backpack => Some("favorite_backpack"),
+        ItemType::Pickaxe => Some("favorite_pickaxe"),
+        ItemType::Glider => Some("favorite_glider"),
+        ItemType::SkyDiveContrail => Some("favorite_skydivecontrail"),
+        ItemType::Dance => Some("favorite_dance"),
+        ItemType::ItemWrap => Some("favorite_itemwraps"),
+        ItemType::MusicPack => Some("favorite_musicpack"),
+        ItemType::LoadingScreen => Some("favorite_loadingscreen"),
+        ItemType::BannerIcon | ItemType::BannerColor | ItemType::MiscItem => None,
+    }
+}
+
+/// The number of items a slot holds. Slots with more than one are stored as arrays.
+pub fn slot_size(slot: &ItemType) -> usize {
+    match slot {
+        ItemType::Dance => 6,
+        ItemType::ItemWrap => 7,
+        _ => 1,
+    }
+}
+
+#[derive(Deserialize)]
+struct EquipBody {
+    #[serde(rename = "slotName")]
+    slot_name: ItemType,
+    #[serde(rename = "itemToSlot", default)]
+    item_to_slot: String,
+    #[serde(rename = "indexWithinSlot", default)]
+    index_within_slot: i64,
+    #[serde(rename = "variantUpdates", default)]
+    variant_updates: Vec<Value>,
+}
+
+#[derive(Deserialize)]
+struct LockerSlotBody {
+    #[serde(rename = "lockerItem")]
+    locker_item: String,
+    category: ItemType,
+    #[serde(rename = "itemToSlot", default)]
+    item_to_slot: String,
+    #[serde(rename = "slotIndex", default)]
+    slot_index: i64,
+    #[serde(rename = "variantUpdates", default)]
+    variant_updates: Vec<Value>,
+}
+
+fn item_not_found(item_id: &str) -> Box<EpicError> {
+    Box::new(make_epic_err(
+        "errors.com.epicgames.fortnite.item_not_found",
+        &format!("Locker item {} not found", item_id),
+        &[item_id.to_string()],
+        16006,
+        StatusCode::NOT_FOUND,
+    ))
+}
+
+fn invalid_slot(message: &str) -> Box<EpicError> {
+    Box::new(make_epic_err(
+        "errors.com.epicgames.validation.validation_failed",
+        message,
+        &[],
+        1040,
+        StatusCode::BAD_REQUEST,
+    ))
+}
+
+/// Makes sure the item can go into the supplied slot. An empty item ID unequips the slot and is
+/// always valid.
+fn validate_item(state: &GlyphState, profile: &Profile, slot: &ItemType, item_id: &str) -> Result<(), Box<EpicError>> {
+    if favorite_stat(slot).is_none() {
+        return Err(invalid_slot(&format!("{:?} is not a valid locker slot", slot)));
+    }
+    if item_id.is_empty() {
+        return Ok(());
+    }
+
+    let Some(item) = profile.items.get(item_id) else {
+        return Err(item_not_found(item_id));
+    };
+    match state.item_manager.load().get_item_type(&item.template_id).copied() {
+        Some(item_type) if item_type == *slot => Ok(()),
+        _ => Err(invalid_slot(&format!("{} can't be equipped in the {:?} slot", item_id, slot))),
+    }
+}
+
+/// Returns the slot indices to change. An index of -1 on a slot with more than one entry changes
+/// every entry, which the client uses to apply a wrap to everything at once.
+fn slot_indices(slot: &ItemType, index: i64) -> Result<Vec<usize>, Box<EpicError>> {
+    let size = slot_size(slot);
+    if index == -1 && size > 1 {
+        return Ok((0..size).collect());
+    }
+    match usize::try_from(index) {
+        Ok(index) if index < size => Ok(vec![index]),
+        _ => Err(invalid_slot(&format!("{} is not a valid index for the {:?} slot", index, slot))),
+    }
+}
+
+/// Writes the item into the supplied indices of a slot, keeping whatever is already in the others.
+fn slot_value(current: Option<&Value>, slot: &ItemType, indices: &[usize], item_id: &str) -> Value {
+    let size = slot_size(slot);
+    if size == 1 {
+        return Value::String(item_id.to_string());
+    }
+
+    let mut items = match current {
+        Some(Value::Array(items)) => items.clone(),
+        _ => vec![],
+    };
+    items.resize(size, Value::String(String::new()));
+    for index in indices {
+        items[*index] = Value::String(item_id.to_string());
+    }
+    Value::Array(items)
+}
+
+fn apply_variants(profile: &mut Profile, item_id: &str, variant_updates: Vec<Value>) {
+    if !item_id.is_empty() && !variant_updates.is_empty() {
+        profile.set_item_attribute(item_id, "variants", Value::Array(variant_updates));
+    }
+}
+
+pub fn equip_battle_royale_customization(
+    state: &GlyphState,
+    profile: &mut Profile,
+    body: &Value,
+) -> Result<CommandOutput, Box<EpicError>> {
+    let body = parse_body::<EquipBody>(body)?;
+    validate_item(state, profile, &body.slot_name, &body.item_to_slot)?;
+    let indices = slot_indices(&body.slot_name, body.index_within_slot)?;
+
+    let stat = favorite_stat(&body.slot_name).expect("validated slot has a stat");
+    let value = slot_value(profile.stat(stat), &body.slot_name, &indices, &body.item_to_slot);
+    profile.set_stat(stat, value);
+    apply_variants(profile, &body.item_to_slot, body.variant_updates);
+
+    Ok(CommandOutput::Changes)
+}
+
+pub fn set_cosmetic_locker_slot(
+    state: &GlyphState,
+    profile: &mut Profile,
+    body: &Value,
+) -> Result<CommandOutput, Box<EpicError>> {
+    let body = parse_body::<LockerSlotBody>(body)?;
+    validate_item(state, profile, &body.category, &body.item_to_slot)?;
+    let indices = slot_indices(&body.category, body.slot_index)?;
+
+    let Some(locker) = profile.items.get(&body.locker_item) else {
+        return Err(item_not_found(&body.locker_item));
+    };
+    if locker.template_id != LOCKER_TEMPLATE_ID {
+        return Err(invalid_slot(&format!("{} is not a locker", body.locker_item)));
+    }
+
+    let category = format!("{:?}", body.category);
+    let mut slots_data = match locker.attributes.get("locker_slots_data") {
+        Some(Value::Object(map)) => map.clone(),
+        _ => Map::new(),
+    };
+    let current = slots_data.get("slots")
+        .and_then(|slots| slots.get(&category))
+        .and_then(|slot| slot.get("items"));
+    let items = slot_value(current, &body.category, &indices, &body.item_to_slot);
+    let mut slots = match slots_data.remove("slots") {
+        Some(Value::Object(map)) => map,
+        _ => Map::new(),
+    };
+    slots.insert(category, json!({ "items": items }));
+    slots_data.insert("slots".to_string(), Value::Object(slots));
+    profile.set_item_attribute(&body.locker_item, "locker_slots_data", Value::Object(slots_data));
+
+    // Older parts of the client still read the favorite stats, so keep them in sync.
+    let stat = favorite_stat(&body.category).expect("validated slot has a stat");
+    let value = slot_value(profile.stat(stat), &body.category, &indices, &body.item_to_slot);
+    profile.set_stat(stat, value);
+    apply_variants(profile, &body.item_to_slot, body.variant_updates);
+
+    Ok(CommandOutput::Changes)
+}
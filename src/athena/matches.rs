@@ -0,0 +1,139 @@
+use crate::athena::items::ItemManager;
+use crate::error;
+use crate::error::Error;
+use crate::mcp::defaults::get_or_create_profile;
+use crate::mcp::profile::{save_profile, Profile, ProfileId, SAVE_ATTEMPTS};
+use crate::mongo::{GlyphMongo, MATCHES_COLL, STATS_DB};
+use crate::{stats, user};
+use crate::util::UuidString;
+use chrono::{DateTime, Utc};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+const XP_PER_ELIMINATION: i64 = 50;
+const XP_PER_MINUTE_ALIVE: i64 = 10;
+const XP_PER_LEVEL: i64 = 1000;
+const MAX_LEVEL: i64 = 100;
+
+/// How one player did in a match, as reported by the server that hosted it.
+#[derive(Deserialize, Clone)]
+pub struct PlayerResult {
+    #[serde(rename = "accountId")]
+    pub(crate) account_id: String,
+    pub(crate) placement: u32,
+    pub(crate) eliminations: u32,
+    /// How long the player was alive for, in seconds.
+    #[serde(rename = "timeAlive")]
+    pub(crate) time_alive: u32,
+    pub(crate) playlist: String,
+}
+
+/// An entry of a player's match history.
+#[derive(Serialize, Deserialize)]
+pub struct MatchRecord {
+    #[serde(rename = "_id")]
+    pub(crate) id: String,
+    pub(crate) account_id: String,
+    pub(crate) session_id: String,
+    pub(crate) server_id: String,
+    pub(crate) playlist: String,
+    pub(crate) placement: u32,
+    pub(crate) eliminations: u32,
+    pub(crate) time_alive: u32,
+    pub(crate) xp_gained: i64,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub(crate) ended_at: DateTime<Utc>,
+}
+
+/// The XP a result is worth. Placing in the top 25 earns a bonus on top of eliminations and
+/// survival time.
+fn xp_for(result: &PlayerResult) -> i64 {
+    let placement_bonus = match result.placement {
+        1 => 300,
+        2..=10 => 100,
+        11..=25 => 50,
+        _ => 0,
+    };
+    placement_bonus
+        + result.eliminations as i64 * XP_PER_ELIMINATION
+        + (result.time_alive / 60) as i64 * XP_PER_MINUTE_ALIVE
+}
+
+fn int_stat(profile: &Profile, name: &str) -> i64 {
+    profile.stat(name).and_then(Value::as_i64).unwrap_or(0)
+}
+
+/// Adds the XP and, if the player won, the win to the athena stats of the profile.
+fn apply_result(profile: &mut Profile, result: &PlayerResult, xp_gained: i64) {
+    let xp = int_stat(profile, "xp") + xp_gained;
+    profile.set_stat("xp", json!(xp));
+    let level = (1 + xp / XP_PER_LEVEL).min(MAX_LEVEL);
+    if level != int_stat(profile, "level") {
+        profile.set_stat("level", json!(level));
+    }
+
+    if result.placement == 1 {
+        profile.set_stat("lifetime_wins", json!(int_stat(profile, "lifetime_wins") + 1));
+        let mut playlist_wins = profile.stat("playlist_wins").and_then(Value::as_object).cloned().unwrap_or_default();
+        let wins = playlist_wins.get(&result.playlist).and_then(Value::as_i64).unwrap_or(0);
+        playlist_wins.insert(result.playlist.clone(), json!(wins + 1));
+        profile.set_stat("playlist_wins", Value::Object(playlist_wins));
+    }
+}
+
+/// Applies a result to the player's athena profile, bumping its revision so the client picks up
+/// the new stats the next time it queries it, then stores it in the player's match history and
+/// [stats]. The result counts as recorded once the profile is saved, the session has ended by
+/// then so failing to store the rest is only logged.
+pub async fn record_result(
+    mongo: &GlyphMongo,
+    item_manager: &ItemManager,
+    server_id: &str,
+    session_id: &str,
+    result: &PlayerResult,
+) -> error::Result<MatchRecord> {
+    let account_id = Uuid::parse_str(&result.account_id).map_err(|_| Error::UserNotFound)?;
+    if user::get_user(mongo, &account_id).await?.is_none() {
+        return Err(Error::UserNotFound);
+    }
+
+    // The player is usually back in the lobby by now, so the profile is reloaded and the result
+    // applied again whenever one of their commands saves it first.
+    let xp_gained = xp_for(result);
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        let mut profile = get_or_create_profile(mongo, &account_id, ProfileId::Athena, item_manager).await?;
+        let base_revision = profile.rvn;
+        apply_result(&mut profile, result, xp_gained);
+        profile.commit();
+        if save_profile(mongo, &profile, base_revision).await? {
+            break;
+        }
+        if attempts >= SAVE_ATTEMPTS {
+            return Err(Error::ProfileConflict(profile.id));
+        }
+    }
+
+    let record = MatchRecord {
+        id: Uuid::new_v4().to_stripped_string(),
+        account_id: account_id.to_stripped_string(),
+        session_id: session_id.to_string(),
+        server_id: server_id.to_string(),
+        playlist: result.playlist.clone(),
+        placement: result.placement,
+        eliminations: result.eliminations,
+        time_alive: result.time_alive,
+        xp_gained,
+        ended_at: Utc::now(),
+    };
+    if let Err(e) = mongo.collection::<MatchRecord>(STATS_DB, MATCHES_COLL).await.insert_one(&record).await {
+        warn!("Failed to store the match history of {}: {}", record.account_id, e);
+    }
+    if let Err(e) = stats::record_result(mongo, &record.account_id, result, xp_gained).await {
+        warn!("Failed to record the stats of {}: {}", record.account_id, e);
+    }
+    Ok(record)
+}
@@ -0,0 +1,239 @@
+use crate::athena::items::{ItemManager, ItemType};
+use crate::error;
+use crate::error::Error;
+use chrono::{DateTime, TimeDelta, Utc};
+use log::info;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Deserializer};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+
+pub const DEFAULT_SHOP_CONFIG_PATH: &str = "./shop.json";
+
+/// The storefront names the client sorts sections into.
+pub const DAILY_STOREFRONT: &str = "BRDailyStorefront";
+pub const FEATURED_STOREFRONT: &str = "BRWeeklyStorefront";
+
+/// How one section of the shop rotates.
+#[derive(Clone)]
+pub struct SectionConfig {
+    /// How many items are on sale at once.
+    pub(crate) size: usize,
+    /// How long a rotation lasts, in hours. Rotations start at multiples of this since the Unix
+    /// epoch, so a 24 hour rotation changes at midnight UTC.
+    pub(crate) rotation_hours: i64,
+    /// The types of item the section sells.
+    pub(crate) item_types: Vec<ItemType>,
+}
+
+impl SectionConfig {
+    /// How long a rotation lasts, in hours. Rotations are at least an hour long.
+    pub fn rotation_hours(&self) -> i64 {
+        self.rotation_hours.max(1)
+    }
+}
+
+/// A [SectionConfig] as written in the file, where every setting can be left out.
+#[derive(Deserialize)]
+struct PartialSection {
+    size: Option<usize>,
+    rotation_hours: Option<i64>,
+    item_types: Option<Vec<ItemType>>,
+}
+
+impl PartialSection {
+    fn or(self, default: SectionConfig) -> SectionConfig {
+        SectionConfig {
+            size: self.size.unwrap_or(default.size),
+            rotation_hours: self.rotation_hours.unwrap_or(default.rotation_hours),
+            item_types: self.item_types.unwrap_or(default.item_types),
+        }
+    }
+}
+
+fn deserialize_daily<'de, D: Deserializer<'de>>(deserializer: D) -> Result<SectionConfig, D::Error> {
+    Ok(PartialSection::deserialize(deserializer)?.or(ShopConfig::default().daily))
+}
+
+fn deserialize_featured<'de, D: Deserializer<'de>>(deserializer: D) -> Result<SectionConfig, D::Error> {
+    Ok(PartialSection::deserialize(deserializer)?.or(ShopConfig::default().featured))
+}
+
+/// Controls what the item shop sells, loaded from the JSON file in `SHOP_CONFIG_PATH`. Anything
+/// left out of the file falls back to its default.
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct ShopConfig {
+    /// Mixed into every rotation, changing it reshuffles the shop without changing any other
+    /// setting.
+    pub(crate) seed: u64,
+    #[serde(deserialize_with = "deserialize_daily")]
+    pub(crate) daily: SectionConfig,
+    #[serde(deserialize_with = "deserialize_featured")]
+    pub(crate) featured: SectionConfig,
+    /// The price of each type in V-Bucks. Types without a price aren't sold.
+    pub(crate) prices: HashMap<ItemType, u32>,
+}
+
+impl Default for ShopConfig {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            daily: SectionConfig {
+                size: 6,
+                rotation_hours: 24,
+                item_types: vec![
+                    ItemType::Backpack,
+                    ItemType::Pickaxe,
+                    ItemType::Glider,
+                    ItemType::SkyDiveContrail,
+                    ItemType::Dance,
+                    ItemType::ItemWrap,
+                    ItemType::MusicPack,
+                    ItemType::LoadingScreen,
+                ],
+            },
+            featured: SectionConfig {
+                size: 2,
+                rotation_hours: 24 * 7,
+                item_types: vec![ItemType::Character],
+            },
+            prices: HashMap::from([
+                (ItemType::Character, 1200),
+                (ItemType::Backpack, 400),
+                (ItemType::Pickaxe, 800),
+                (ItemType::Glider, 800),
+                (ItemType::SkyDiveContrail, 300),
+                (ItemType::Dance, 500),
+                (ItemType::ItemWrap, 300),
+                (ItemType::MusicPack, 200),
+                (ItemType::LoadingScreen, 100),
+            ]),
+        }
+    }
+}
+
+impl ShopConfig {
+    /// Loads the config from the path in `SHOP_CONFIG_PATH`, or [DEFAULT_SHOP_CONFIG_PATH] if it
+    /// isn't set. The defaults are used if there is no file at that path.
+    pub fn new() -> error::Result<Self> {
+        let path = std::env::var("SHOP_CONFIG_PATH").unwrap_or_else(|_| DEFAULT_SHOP_CONFIG_PATH.to_string());
+        let file = match fs::read_to_string(&path) {
+            Ok(val) => val,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                info!("No shop config at {}, using the defaults", path);
+                return Ok(ShopConfig::default());
+            }
+            Err(e) => return Err(Error::ShopConfigRead(path, e)),
+        };
+        serde_json::from_str(&file).map_err(|e| Error::MalformedShopConfig(path, e))
+    }
+}
+
+pub struct ShopEntry {
+    pub(crate) template_id: String,
+    pub(crate) item_type: ItemType,
+    pub(crate) price: u32,
+}
+
+/// The items one section sells during the current rotation.
+pub struct ShopSection {
+    pub(crate) storefront: &'static str,
+    pub(crate) entries: Vec<ShopEntry>,
+    pub(crate) expires_at: DateTime<Utc>,
+}
+
+/// Every section of the shop at one point in time, see [current_shop].
+pub struct Shop {
+    pub(crate) sections: Vec<ShopSection>,
+}
+
+impl Shop {
+    /// When the first section rotates.
+    pub fn expires_at(&self) -> DateTime<Utc> {
+        self.sections.iter().map(|section| section.expires_at).min().unwrap_or_else(Utc::now)
+    }
+}
+
+/// Derives the RNG seed of one rotation of a section, so the same config and catalogue always
+/// produce the same shop.
+fn rotation_seed(seed: u64, storefront: &str, rotation: i64) -> u64 {
+    let mut hasher = Sha256::new();
+    hasher.update(seed.to_le_bytes());
+    hasher.update(storefront.as_bytes());
+    hasher.update(rotation.to_le_bytes());
+    let digest = hasher.finalize();
+    u64::from_le_bytes(digest[..8].try_into().expect("a SHA-256 digest is 32 bytes"))
+}
+
+fn build_section(
+    config: &ShopConfig,
+    section: &SectionConfig,
+    storefront: &'static str,
+    item_manager: &ItemManager,
+    exclude: &[String],
+    now: DateTime<Utc>,
+) -> ShopSection {
+    let period = TimeDelta::hours(section.rotation_hours());
+    let rotation = now.timestamp().div_euclid(period.num_seconds());
+    let expires_at = DateTime::from_timestamp((rotation + 1) * period.num_seconds(), 0).unwrap_or(now + period);
+
+    let candidates = section.item_types.iter()
+        .filter_map(|item_type| Some((item_type, *config.prices.get(item_type)?)))
+        .flat_map(|(item_type, price)| item_manager.items_of(item_type).iter().map(move |id| (id, *item_type, price)))
+        .filter(|(id, _, _)| !exclude.contains(id))
+        .collect::<Vec<_>>();
+
+    let mut rng = ChaCha8Rng::seed_from_u64(rotation_seed(config.seed, storefront, rotation));
+    let entries = candidates.choose_multiple(&mut rng, section.size)
+        .map(|(id, item_type, price)| ShopEntry {
+            template_id: id.to_string(),
+            item_type: *item_type,
+            price: *price,
+        })
+        .collect();
+
+    ShopSection { storefront, entries, expires_at }
+}
+
+/// Picks the items on sale at the supplied time. Featured items are picked first and never also
+/// show up in the daily section.
+pub fn shop_at(config: &ShopConfig, item_manager: &ItemManager, now: DateTime<Utc>) -> Shop {
+    let featured = build_section(config, &config.featured, FEATURED_STOREFRONT, item_manager, &[], now);
+    let featured_ids = featured.entries.iter().map(|entry| entry.template_id.clone()).collect::<Vec<_>>();
+    let daily = build_section(config, &config.daily, DAILY_STOREFRONT, item_manager, &featured_ids, now);
+    Shop { sections: vec![featured, daily] }
+}
+
+pub fn current_shop(config: &ShopConfig, item_manager: &ItemManager) -> Shop {
+    shop_at(config, item_manager, Utc::now())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shop_at_is_reproducible() {
+        let listed = HashMap::from([
+            (ItemType::Character, (0..20).map(|i| format!("AthenaCharacter:cid_{:03}", i)).collect()),
+            (ItemType::Pickaxe, (0..20).map(|i| format!("AthenaPickaxe:pickaxe_{:03}", i)).collect()),
+        ]);
+        let item_manager = ItemManager::from_listed("test", listed).unwrap();
+        let config = ShopConfig::default();
+        let now = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+
+        let template_ids = |shop: Shop| shop.sections.into_iter()
+            .flat_map(|section| section.entries)
+            .map(|entry| entry.template_id)
+            .collect::<Vec<_>>();
+        let first = template_ids(shop_at(&config, &item_manager, now));
+        let second = template_ids(shop_at(&config, &item_manager, now));
+        assert!(!first.is_empty());
+        assert_eq!(first, second);
+    }
+}
@@ -4,14 +4,15 @@ use crate::user::User;
 use crate::util::UuidString;
 use crate::error;
 use chrono::{DateTime, TimeDelta, Utc};
-use hmac::{Hmac, Mac};
-use jwt::SignWithKey;
+use crate::signing_key::{load_signing_keys, rotate_signing_key, SigningKeys};
+use arc_swap::ArcSwap;
+use jwt::{AlgorithmType, Header, SignWithKey, Token, VerifyWithStore};
 use mongodb::bson::doc;
 use mongodb::Collection;
 use serde::{Deserialize, Serialize};
-use sha2::Sha256;
 use std::collections::BTreeMap;
 use std::ops::Add;
+use std::sync::Arc;
 use uuid::Uuid;
 
 #[derive(Serialize, Deserialize)]
@@ -22,39 +23,96 @@ pub struct OAuthToken {
     pub(crate) expires_at: DateTime<Utc>,
     #[serde(rename = "expireAfterSeconds")]
     expire_after_seconds: i64,
+    /// Shared by every access and refresh token issued from one sign in, so the whole chain can be
+    /// killed at once. See [OAuthManager::kill_token_family].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) family_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) device_id: Option<String>,
+    /// Set once a refresh token has been exchanged, see [OAuthManager::use_refresh_token].
+    #[serde(default)]
+    pub(crate) used: bool,
+    /// The only client allowed to redeem an exchange code, if it is bound to one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) client_id: Option<String>,
+}
+
+/// The outcome of [OAuthManager::use_refresh_token].
+pub enum RefreshTokenUse {
+    /// The token was unused and has now been marked as used.
+    Valid(OAuthToken),
+    /// The token was already exchanged once, so whoever sent it may have stolen it.
+    Reused(OAuthToken),
+    /// The token doesn't exist or has expired.
+    Invalid,
 }
 
 pub struct OAuthManager {
-    signing_key: Hmac<Sha256>,
+    signing_keys: ArcSwap<SigningKeys>,
 }
 
+/// How long an exchange code can be redeemed for by default, in seconds.
+const EXCHANGE_CODE_LIFETIME: i64 = 300;
+
 const P_CLAIM: &str = "eNqtk8lOAzEMht+nQkhlO1iaA0tBnEBC4joyiWdqNeNUiVPo2+NhGShLBxCnbF7+/0vSxKTCSuBCLD5rTNgS5HVW6uCcUEsif5kDij/Y5aexmh7tND9P2x9NK5kSuCgNt9Xe9tK3i5lnPSPPDpX8DaUVpQvsaJeFR4XdLq4DrrdkY9NwYDuDZbkL7GDYGBE2pvtfFc+kZRnyAxZxcyPo472EiB4CrwgmJilHxxhACbsMkqGR6mjHCmGILeQ52h1BbBpK2YI/15nA+Yu20yhKoieFg+9j0blYRAdKz8tq+isImzZeS0YsOgd60Pppck+tsYKEHOpMOXOUWtktSKvD7d16AFQCakK3YBkM2w5lxTYRdebps5u2YPKMks3P10Z7eZQEw7FJvJKwtsgPWCfv6r6M2YB2pOgtEubLun/2Nft6maL/07vfBPiLLzl99yVNxIodsRgTcfQNtAHX3doa97cwpniz495bx0dUELK5";
 
 impl OAuthManager {
-    pub fn new(
-        signing_key: Hmac<Sha256>,
-    ) -> Self {
-        Self { signing_key }
+    /// Creates a manager that signs with the stored signing keys, generating the first one if
+    /// there are none.
+    pub async fn new(mongo: &GlyphMongo) -> error::Result<Self> {
+        let signing_keys = load_signing_keys(mongo).await?;
+        Ok(Self { signing_keys: ArcSwap::from_pointee(signing_keys) })
+    }
+
+    /// Replaces the active signing key with a new one and returns its key ID. Tokens signed with
+    /// the old key stay valid until they expire.
+    pub async fn rotate_signing_key(&self, mongo: &GlyphMongo) -> error::Result<String> {
+        let signing_keys = rotate_signing_key(mongo).await?;
+        let kid = signing_keys.active_kid.clone();
+        self.signing_keys.store(Arc::new(signing_keys));
+        Ok(kid)
+    }
+
+    /// Verifies the signature of a token against every signing key that hasn't expired, then
+    /// checks its `exp` claim and returns its claims.
+    pub fn verify_token(&self, token: &str) -> error::Result<BTreeMap<String, String>> {
+        let signing_keys = self.signing_keys.load();
+        let claims: BTreeMap<String, String> = token.verify_with_store(&**signing_keys)
+            .map_err(|_| error::Error::InvalidToken)?;
+        let exp = claims.get("exp")
+            .and_then(|exp| exp.parse::<i64>().ok())
+            .ok_or(error::Error::InvalidToken)?;
+        if exp <= Utc::now().timestamp() {
+            return Err(error::Error::TokenExpired);
+        }
+        Ok(claims)
     }
 
-    /// Generated an HMAC SHA256 key for signing JWT tokens with the supplied UUID.
-    pub(crate) fn gen_signing_key(
-        runner_id: Uuid,
-    ) -> error::Result<Hmac<Sha256>> {
-        let key: Hmac<Sha256> = Hmac::new_from_slice(runner_id.to_stripped_string().as_bytes())?;
-        Ok(key.clone())
+    /// Signs the supplied claims with the active signing key, setting the `kid` header so the
+    /// token can still be verified after the key is rotated.
+    fn sign(&self, claims: BTreeMap<&str, &str>) -> error::Result<String> {
+        let signing_keys = self.signing_keys.load();
+        let (kid, key) = signing_keys.active();
+        let header = Header {
+            algorithm: AlgorithmType::Hs256,
+            key_id: Some(kid.to_string()),
+            ..Default::default()
+        };
+        let token = Token::new(header, claims).sign_with_key(key)?;
+        Ok(token.as_str().to_string())
     }
 
-    /// Signs, stores, then returns a refresh token for the supplied [User] to be passed to the
+    /// Signs, stores, then returns an exchange code for the supplied [User] to be passed to the
     /// client on startup. This is used instead of the game's password auth because users use
-    /// Discord to sign in.
+    /// Discord to sign in. If a client ID is supplied, only that client can redeem the code.
     pub async fn make_exchange_code(
         &self,
         mongo: &GlyphMongo,
         user: &User,
+        client_id: Option<&str>,
         expires_at: Option<i64>,
     ) -> error::Result<OAuthToken> {
-        let expires_at = expires_at.unwrap_or(28800);
+        let expires_at = expires_at.unwrap_or(EXCHANGE_CODE_LIFETIME);
         let expiration = Utc::now().add(TimeDelta::seconds(expires_at));
 
         let mut claims = BTreeMap::new();
@@ -66,26 +124,41 @@ impl OAuthManager {
         let exp = expiration.timestamp().to_string();
         claims.insert("exp", exp.as_str());
 
-        let token = claims.sign_with_key(&self.signing_key)?;
+        let token = self.sign(claims)?;
         let oauth_token = OAuthToken {
             token,
             account_id: user.account_id,
             expires_at: expiration,
             expire_after_seconds: 0,
+            family_id: None,
+            device_id: None,
+            used: false,
+            client_id: client_id.map(str::to_string),
         };
 
         mongo.collection::<OAuthToken>(AUTH_DB, EXCHANGE_CODE_COLL).await.insert_one(&oauth_token).await?;
         Ok(oauth_token)
     }
 
-    /// See [OAuthManager::get_token]
-    pub async fn get_exchange_code(mongo: &GlyphMongo, token: &String) -> error::Result<Option<OAuthToken>> {
-        OAuthManager::get_token(mongo.collection::<OAuthToken>(AUTH_DB, EXCHANGE_CODE_COLL).await, token).await
+    /// Finds and deletes an exchange code in one operation, so two requests can never redeem the
+    /// same code. The code is returned even if it has expired so the caller can tell the client
+    /// why it was rejected. Codes bound to a different client are left in place, see
+    /// [OAuthManager::exchange_code_client].
+    pub async fn redeem_exchange_code(mongo: &GlyphMongo, token: &str, client_id: &str) -> error::Result<Option<OAuthToken>> {
+        let filter = doc! {
+            "token": token,
+            "$or": [{ "client_id": null }, { "client_id": client_id }],
+        };
+        let collection = mongo.collection::<OAuthToken>(AUTH_DB, EXCHANGE_CODE_COLL).await;
+        Ok(collection.find_one_and_delete(filter).await?)
     }
 
-    /// See [OAuthManager::kill_token]
-    pub async fn kill_exchange_code(mongo: &GlyphMongo, token: &String) -> error::Result<bool> {
-        OAuthManager::kill_token(mongo.collection::<OAuthToken>(AUTH_DB, EXCHANGE_CODE_COLL).await, token).await
+    /// Returns the client a stored exchange code is bound to, or [None] if the code doesn't exist
+    /// or isn't bound to one.
+    pub async fn exchange_code_client(mongo: &GlyphMongo, token: &str) -> error::Result<Option<String>> {
+        let collection = mongo.collection::<OAuthToken>(AUTH_DB, EXCHANGE_CODE_COLL).await;
+        let exchange_code = collection.find_one(doc! { "token": token }).await?;
+        Ok(exchange_code.and_then(|exchange_code| exchange_code.client_id))
     }
 
     /// Signs and returns a client token. This is just used to tell the client it can actually play
@@ -112,17 +185,41 @@ impl OAuthManager {
         claims.insert("jti", jti.as_str());
         claims.insert("pfpid", "prod-fn");
 
-        let token = claims.sign_with_key(&self.signing_key)?;
+        let token = self.sign(claims)?;
         Ok(token)
     }
 
+    /// Signs a matchmaking ticket with the supplied claims that expires after five minutes. The
+    /// token is split into its payload and signature because the client sends them back to the
+    /// matchmaker separately, see [OAuthManager::verify_matchmaking_ticket].
+    pub fn make_matchmaking_ticket(&self, ticket_claims: &BTreeMap<&str, &str>) -> error::Result<(String, String)> {
+        let exp = Utc::now().add(TimeDelta::minutes(5)).timestamp().to_string();
+        let mut claims = ticket_claims.clone();
+        claims.insert("exp", exp.as_str());
+        claims.insert("t", "mms");
+        let token = self.sign(claims)?;
+        let (payload, signature) = token.rsplit_once('.').ok_or(error::Error::InvalidToken)?;
+        Ok((payload.to_string(), signature.to_string()))
+    }
+
+    /// Verifies a ticket from [OAuthManager::make_matchmaking_ticket] and returns its claims.
+    pub fn verify_matchmaking_ticket(&self, payload: &str, signature: &str) -> error::Result<BTreeMap<String, String>> {
+        let claims = self.verify_token(&format!("{}.{}", payload, signature))?;
+        if claims.get("t").map(String::as_str) != Some("mms") {
+            return Err(error::Error::InvalidToken);
+        }
+        Ok(claims)
+    }
+
     /// Signs, stores, then returns an access token for the supplied [User].
+    #[allow(clippy::too_many_arguments)]
     pub async fn make_access_token(
         &self,
         mongo: &GlyphMongo,
         user: &User,
         client_id: &str,
         device_id: &str,
+        family_id: &str,
         grant_type: &GrantType,
         expires_in: Option<i64>,
     ) -> error::Result<OAuthToken> {
@@ -153,12 +250,16 @@ impl OAuthManager {
         let exp = expiration.timestamp().to_string();
         claims.insert("exp", exp.as_str());
 
-        let token = claims.sign_with_key(&self.signing_key)?;
+        let token = self.sign(claims)?;
         let oauth_token = OAuthToken {
             token,
             account_id: user.account_id,
             expires_at: expiration,
             expire_after_seconds: 0,
+            family_id: Some(family_id.to_string()),
+            device_id: Some(device_id.to_string()),
+            used: false,
+            client_id: Some(client_id.to_string()),
         };
 
         mongo.collection::<OAuthToken>(AUTH_DB, ACCESS_TKN_COLL).await.insert_one(&oauth_token).await?;
@@ -175,13 +276,34 @@ impl OAuthManager {
         OAuthManager::kill_token(mongo.collection::<OAuthToken>(AUTH_DB, ACCESS_TKN_COLL).await, token).await
     }
 
+    /// Kills every access and refresh token belonging to the supplied [User] except those issued
+    /// from the same sign in as `keep`.
+    pub async fn kill_other_sessions(mongo: &GlyphMongo, user: &User, keep: &OAuthToken) -> error::Result<()> {
+        let account_id = user.account_id.to_string();
+        let (access_filter, refresh_filter) = match &keep.family_id {
+            Some(family_id) => (
+                doc! { "account_id": &account_id, "family_id": { "$ne": family_id } },
+                doc! { "account_id": &account_id, "family_id": { "$ne": family_id } },
+            ),
+            None => (
+                doc! { "account_id": &account_id, "token": { "$ne": &keep.token } },
+                doc! { "account_id": &account_id },
+            ),
+        };
+        mongo.collection::<OAuthToken>(AUTH_DB, ACCESS_TKN_COLL).await.delete_many(access_filter).await?;
+        mongo.collection::<OAuthToken>(AUTH_DB, REFRESH_TKN_COLL).await.delete_many(refresh_filter).await?;
+        Ok(())
+    }
+
     /// Signs, stores, then returns a refresh token for the supplied [User].
+    #[allow(clippy::too_many_arguments)]
     pub async fn make_refresh_token(
         &self,
         mongo: &GlyphMongo,
         user: &User,
         client_id: &str,
         device_id: &str,
+        family_id: &str,
         grant_type: &GrantType,
         expires_at: Option<i64>,
     ) -> error::Result<OAuthToken> {
@@ -204,12 +326,16 @@ impl OAuthManager {
         let exp = expiration.timestamp().to_string();
         claims.insert("exp", exp.as_str());
 
-        let token = claims.sign_with_key(&self.signing_key)?;
+        let token = self.sign(claims)?;
         let oauth_token = OAuthToken {
             token,
             account_id: user.account_id,
             expires_at: expiration,
             expire_after_seconds: 0,
+            family_id: Some(family_id.to_string()),
+            device_id: Some(device_id.to_string()),
+            used: false,
+            client_id: Some(client_id.to_string()),
         };
 
         mongo.collection::<OAuthToken>(AUTH_DB, REFRESH_TKN_COLL).await.insert_one(&oauth_token).await?;
@@ -221,9 +347,33 @@ impl OAuthManager {
         OAuthManager::get_token(mongo.collection::<OAuthToken>(AUTH_DB, REFRESH_TKN_COLL).await, token).await
     }
 
-    /// See [OAuthManager::kill_token]
-    pub async fn kill_refresh_token(mongo: &GlyphMongo, token: &str) -> error::Result<bool> {
-        OAuthManager::kill_token(mongo.collection::<OAuthToken>(AUTH_DB, ACCESS_TKN_COLL).await, token).await
+    /// Atomically marks a refresh token as used so that it can only be exchanged once. Used tokens
+    /// are kept until they expire so that a second attempt to exchange one can be detected. Tokens
+    /// issued to a different client are treated as invalid and left untouched.
+    pub async fn use_refresh_token(mongo: &GlyphMongo, token: &str, client_id: &str) -> error::Result<RefreshTokenUse> {
+        let collection = mongo.collection::<OAuthToken>(AUTH_DB, REFRESH_TKN_COLL).await;
+        let filter = doc! {
+            "token": token,
+            "client_id": client_id,
+            "used": { "$ne": true },
+            "expireAt": { "$gt": bson::DateTime::from_chrono(Utc::now()) },
+        };
+        if let Some(found) = collection.find_one_and_update(filter, doc! { "$set": { "used": true } }).await? {
+            return Ok(RefreshTokenUse::Valid(found));
+        }
+
+        match OAuthManager::get_refresh_token(mongo, token).await? {
+            Some(found) if found.used && found.client_id.as_deref() == Some(client_id) => Ok(RefreshTokenUse::Reused(found)),
+            _ => Ok(RefreshTokenUse::Invalid),
+        }
+    }
+
+    /// Kills every access and refresh token issued from the same sign in.
+    pub async fn kill_token_family(mongo: &GlyphMongo, family_id: &str) -> error::Result<()> {
+        let filter = doc! { "family_id": family_id };
+        mongo.collection::<OAuthToken>(AUTH_DB, ACCESS_TKN_COLL).await.delete_many(filter.clone()).await?;
+        mongo.collection::<OAuthToken>(AUTH_DB, REFRESH_TKN_COLL).await.delete_many(filter).await?;
+        Ok(())
     }
 
     /// Kills all exchange codes, access tokens, and refresh tokens for the supplied [User].
@@ -1,5 +1,7 @@
 use crate::discord::commands;
+use crate::role;
 use crate::{ChannelCommand, GlyphState};
+use log::warn;
 use poise::serenity_prelude as serenity;
 use poise::serenity_prelude::ActivityData;
 use std::collections::HashSet;
@@ -17,15 +19,35 @@ pub type Context<'a> = poise::Context<'a, BotState, CommandError>;
 pub(crate) async fn start_bot(state: Arc<GlyphState>, tx: Sender<ChannelCommand>) -> serenity::Result<serenity::Client> {
     let token = std::env::var("DISCORD_TOKEN").expect("Missing DISCORD_TOKEN");
     let intents = serenity::GatewayIntents::all();
+    let owners = role::owner_ids();
+    if owners.is_empty() {
+        warn!("OWNER_IDS is empty, nobody will be able to run owner commands");
+    }
 
     let framework = poise::Framework::builder()
         .options(poise::FrameworkOptions {
-            commands: vec![commands::misc::ping(), commands::user::create_user()],
+            commands: vec![
+                commands::misc::ping(),
+                commands::user::create_user(),
+                commands::user::register(),
+                commands::user::login(),
+                commands::user::rename(),
+                commands::friend::friend(),
+                commands::moderation::ban(),
+                commands::moderation::unban(),
+                commands::moderation::bans(),
+                commands::role::role(),
+                commands::items::reload_items(),
+                commands::shop::shop(),
+                commands::auth::rotate_signing_key(),
+                commands::servers::servers(),
+                commands::stats::stats(),
+            ],
             prefix_options: poise::PrefixFrameworkOptions {
                 prefix: Some("glyph!".into()),
                 ..Default::default()
             },
-            owners: HashSet::from([serenity::UserId::from(363474138549059604)]),
+            owners: owners.into_iter().map(serenity::UserId::new).collect::<HashSet<_>>(),
             ..Default::default()
         })
         .setup(|ctx, _ready, framework| {
@@ -0,0 +1,29 @@
+use crate::discord::bot::{CommandError, Context};
+use crate::discord::permissions::is_owner;
+use poise::serenity_prelude as serenity;
+use poise::serenity_prelude::colours::roles::GREEN;
+
+/// Signs new tokens with a freshly generated key. Existing tokens keep working until they expire.
+#[poise::command(slash_command, prefix_command, check = "is_owner")]
+pub async fn rotate_signing_key(
+    ctx: Context<'_>,
+) -> Result<(), CommandError> {
+    let state = &ctx.data().global_state;
+    match state.auth_manager.rotate_signing_key(&state.mongo).await {
+        Ok(kid) => {
+            ctx.send(
+                poise::CreateReply::default().embed(
+                    serenity::CreateEmbed::default()
+                        .title("Signing Key Rotated")
+                        .description(format!("New key ID: {kid}", kid = kid))
+                        .color(GREEN),
+                ),
+            ).await?;
+            Ok(())
+        }
+        Err(e) => {
+            ctx.reply(format!("Failed to rotate the signing key: {}", e)).await?;
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,274 @@
+use crate::discord::bot::{CommandError, Context};
+use crate::friends;
+use crate::friends::{FriendRequestOutcome, Friendship};
+use crate::user;
+use crate::user::User;
+use crate::util::UuidString;
+use poise::serenity_prelude as serenity;
+use poise::serenity_prelude::colours::roles::{GREEN, RED};
+use uuid::Uuid;
+
+/// The most entries listed per section before the rest are summarized.
+const MAX_LISTED: usize = 25;
+
+/// The most characters Discord allows in an embed field value and an embed description.
+const FIELD_LIMIT: usize = 1024;
+const DESCRIPTION_LIMIT: usize = 4096;
+
+/// Room left at the end of a list for the line that summarizes the entries that didn't fit.
+const SUMMARY_RESERVE: usize = 32;
+
+/// Replies and returns [None] if the author doesn't have an account.
+async fn own_account(ctx: Context<'_>) -> Result<Option<User>, CommandError> {
+    match user::get_user_by_discord_id(&ctx.data().global_state.mongo, ctx.author().id.into()).await? {
+        Some(val) => Ok(Some(val)),
+        None => {
+            ctx.reply("You don't have an account yet, use /register to create one.").await?;
+            Ok(None)
+        }
+    }
+}
+
+/// Resolves a target given as a Discord mention or a Glyph display name, replying and returning
+/// [None] if no account matches.
+pub(crate) async fn find_target(ctx: Context<'_>, target: &str) -> Result<Option<User>, CommandError> {
+    let mongo = &ctx.data().global_state.mongo;
+    let found = match serenity::parse_user_mention(target) {
+        Some(discord_id) => user::get_user_by_discord_id(mongo, discord_id.into()).await?,
+        None => user::get_user_by_display_name(mongo, target.trim()).await?,
+    };
+    if found.is_none() {
+        ctx.reply(format!("Couldn't find an account for {}.", target)).await?;
+    }
+    Ok(found)
+}
+
+/// Looks up the display names of the other side of each relationship, falling back to the
+/// account ID for accounts that no longer exist. Entries that would take the list over
+/// `max_chars` are summarized instead.
+async fn describe(ctx: Context<'_>, account: &User, friendships: &[Friendship], max_chars: usize) -> Result<String, CommandError> {
+    if friendships.is_empty() {
+        return Ok("None".to_string());
+    }
+
+    let own_id = account.account_id.to_stripped_string();
+    let other_ids = friendships.iter()
+        .take(MAX_LISTED)
+        .filter_map(|friendship| Uuid::parse_str(friendship.other(&own_id)).ok())
+        .collect::<Vec<_>>();
+    let users = user::get_users(&ctx.data().global_state.mongo, &other_ids).await?;
+
+    let lines = other_ids.iter()
+        .map(|id| match users.iter().find(|user| user.account_id == *id) {
+            Some(user) => format!("{} (<@{}>)", user.display_name, user.discord_id),
+            None => id.to_stripped_string(),
+        });
+
+    let mut described = String::new();
+    let mut listed = 0;
+    for line in lines {
+        if described.chars().count() + line.chars().count() + 1 > max_chars - SUMMARY_RESERVE {
+            break;
+        }
+        described.push_str(&line);
+        described.push('\n');
+        listed += 1;
+    }
+    if friendships.len() > listed {
+        described.push_str(&format!("... and {} more", friendships.len() - listed));
+    }
+    Ok(described.trim_end().to_string())
+}
+
+#[poise::command(slash_command, prefix_command, subcommands("add", "remove", "list", "requests", "block", "unblock"), subcommand_required)]
+pub async fn friend(_ctx: Context<'_>) -> Result<(), CommandError> {
+    Ok(())
+}
+
+/// Sends a friend request, or accepts one they already sent you.
+#[poise::command(slash_command, prefix_command, ephemeral)]
+pub async fn add(
+    ctx: Context<'_>,
+    #[description = "A display name or Discord mention"] target: String,
+) -> Result<(), CommandError> {
+    let Some(account) = own_account(ctx).await? else {
+        return Ok(());
+    };
+    let Some(target) = find_target(ctx, &target).await? else {
+        return Ok(());
+    };
+
+    let state = &ctx.data().global_state;
+    let description = match friends::send_friend_request(&state.mongo, &account.account_id, &target.account_id).await {
+        Ok(FriendRequestOutcome::Sent(friendship)) => {
+            friends::notify_friendship(&state.xmpp, &friendship);
+            format!("Sent a friend request to {}.", target.display_name)
+        }
+        Ok(FriendRequestOutcome::Accepted(friendship)) => {
+            friends::notify_friendship(&state.xmpp, &friendship);
+            format!("You're now friends with {}.", target.display_name)
+        }
+        Err(e) => {
+            ctx.reply(format!("Failed to add {}: {}", target.display_name, e)).await?;
+            return Ok(());
+        }
+    };
+    ctx.send(
+        poise::CreateReply::default().embed(
+            serenity::CreateEmbed::default()
+                .title("Friend Added")
+                .description(description)
+                .color(GREEN),
+        ),
+    ).await?;
+    Ok(())
+}
+
+/// Removes a friend, or declines or cancels a friend request.
+#[poise::command(slash_command, prefix_command, ephemeral)]
+pub async fn remove(
+    ctx: Context<'_>,
+    #[description = "A display name or Discord mention"] target: String,
+) -> Result<(), CommandError> {
+    let Some(account) = own_account(ctx).await? else {
+        return Ok(());
+    };
+    let Some(target) = find_target(ctx, &target).await? else {
+        return Ok(());
+    };
+
+    let state = &ctx.data().global_state;
+    match friends::remove_friend(&state.mongo, &account.account_id, &target.account_id).await {
+        Ok(removed) => {
+            friends::notify_removal(&state.xmpp, &removed);
+            ctx.send(
+                poise::CreateReply::default().embed(
+                    serenity::CreateEmbed::default()
+                        .title("Friend Removed")
+                        .description(format!("{} is no longer your friend or pending request.", target.display_name))
+                        .color(GREEN),
+                ),
+            ).await?;
+            Ok(())
+        }
+        Err(e) => {
+            ctx.reply(format!("Failed to remove {}: {}", target.display_name, e)).await?;
+            Ok(())
+        }
+    }
+}
+
+/// Lists your friends.
+#[poise::command(slash_command, prefix_command, ephemeral)]
+pub async fn list(
+    ctx: Context<'_>,
+) -> Result<(), CommandError> {
+    let Some(account) = own_account(ctx).await? else {
+        return Ok(());
+    };
+
+    let friends = friends::get_friends(&ctx.data().global_state.mongo, &account.account_id).await?;
+    ctx.send(
+        poise::CreateReply::default().embed(
+            serenity::CreateEmbed::default()
+                .title(format!("Friends ({})", friends.len()))
+                .description(describe(ctx, &account, &friends, DESCRIPTION_LIMIT).await?)
+                .color(GREEN),
+        ),
+    ).await?;
+    Ok(())
+}
+
+/// Lists your incoming and outgoing friend requests.
+#[poise::command(slash_command, prefix_command, ephemeral)]
+pub async fn requests(
+    ctx: Context<'_>,
+) -> Result<(), CommandError> {
+    let Some(account) = own_account(ctx).await? else {
+        return Ok(());
+    };
+
+    let summary = friends::get_summary(&ctx.data().global_state.mongo, &account.account_id).await?;
+    ctx.send(
+        poise::CreateReply::default().embed(
+            serenity::CreateEmbed::default()
+                .title("Friend Requests")
+                .field(format!("Incoming ({})", summary.incoming.len()), describe(ctx, &account, &summary.incoming, FIELD_LIMIT).await?, false)
+                .field(format!("Outgoing ({})", summary.outgoing.len()), describe(ctx, &account, &summary.outgoing, FIELD_LIMIT).await?, false)
+                .color(GREEN),
+        ),
+    ).await?;
+    Ok(())
+}
+
+/// Blocks a player, removing them as a friend.
+#[poise::command(slash_command, prefix_command, ephemeral)]
+pub async fn block(
+    ctx: Context<'_>,
+    #[description = "A display name or Discord mention"] target: String,
+) -> Result<(), CommandError> {
+    let Some(account) = own_account(ctx).await? else {
+        return Ok(());
+    };
+    let Some(target) = find_target(ctx, &target).await? else {
+        return Ok(());
+    };
+
+    let state = &ctx.data().global_state;
+    if friends::has_blocked(&state.mongo, &account.account_id, &target.account_id).await? {
+        ctx.reply(format!("You've already blocked {}.", target.display_name)).await?;
+        return Ok(());
+    }
+
+    match friends::block(&state.mongo, &account.account_id, &target.account_id).await {
+        Ok(removed) => {
+            if let Some(removed) = removed {
+                friends::notify_removal(&state.xmpp, &removed);
+            }
+            friends::notify_block(&state.xmpp, &account.account_id, &target.account_id, true);
+        }
+        Err(e) => {
+            ctx.reply(format!("Failed to block {}: {}", target.display_name, e)).await?;
+            return Ok(());
+        }
+    }
+    ctx.send(
+        poise::CreateReply::default().embed(
+            serenity::CreateEmbed::default()
+                .title("Player Blocked")
+                .description(format!("You blocked {}.", target.display_name))
+                .color(RED),
+        ),
+    ).await?;
+    Ok(())
+}
+
+/// Unblocks a player you blocked.
+#[poise::command(slash_command, prefix_command, ephemeral)]
+pub async fn unblock(
+    ctx: Context<'_>,
+    #[description = "A display name or Discord mention"] target: String,
+) -> Result<(), CommandError> {
+    let Some(account) = own_account(ctx).await? else {
+        return Ok(());
+    };
+    let Some(target) = find_target(ctx, &target).await? else {
+        return Ok(());
+    };
+
+    let state = &ctx.data().global_state;
+    if !friends::unblock(&state.mongo, &account.account_id, &target.account_id).await? {
+        ctx.reply(format!("You haven't blocked {}.", target.display_name)).await?;
+        return Ok(());
+    }
+    friends::notify_block(&state.xmpp, &account.account_id, &target.account_id, false);
+    ctx.send(
+        poise::CreateReply::default().embed(
+            serenity::CreateEmbed::default()
+                .title("Player Unblocked")
+                .description(format!("You unblocked {}.", target.display_name))
+                .color(GREEN),
+        ),
+    ).await?;
+    Ok(())
+}
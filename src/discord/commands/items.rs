@@ -0,0 +1,52 @@
+use crate::athena::items::ItemDiff;
+use crate::discord::bot::{CommandError, Context};
+use crate::discord::permissions::is_owner;
+use poise::serenity_prelude as serenity;
+use poise::serenity_prelude::colours::roles::GREEN;
+use std::sync::Arc;
+
+/// The most item IDs listed per type before the rest are summarized.
+const MAX_LISTED: usize = 10;
+
+fn describe_diff(diff: &ItemDiff) -> String {
+    let changes = diff.added.iter().map(|id| format!("+ {}", id))
+        .chain(diff.removed.iter().map(|id| format!("- {}", id)))
+        .collect::<Vec<String>>();
+
+    let mut listed = changes.iter().take(MAX_LISTED).cloned().collect::<Vec<String>>();
+    if changes.len() > MAX_LISTED {
+        listed.push(format!("...and {} more", changes.len() - MAX_LISTED));
+    }
+    format!("```diff\n{}\n```", listed.join("\n"))
+}
+
+/// Reloads the item catalogue from disk and posts which items were added or removed.
+#[poise::command(slash_command, prefix_command, check = "is_owner")]
+pub async fn reload_items(
+    ctx: Context<'_>,
+) -> Result<(), CommandError> {
+    let item_manager = &ctx.data().global_state.item_manager;
+    let reloaded = match item_manager.load().reload() {
+        Ok(val) => val,
+        Err(e) => {
+            ctx.reply(format!("Failed to reload items: {}", e)).await?;
+            return Ok(());
+        }
+    };
+    let previous = item_manager.swap(Arc::new(reloaded));
+    let diffs = previous.diff(&item_manager.load());
+
+    let mut embed = serenity::CreateEmbed::default()
+        .title("Items Reloaded")
+        .color(GREEN);
+    if diffs.is_empty() {
+        embed = embed.description("No items were added or removed.");
+    }
+    for diff in &diffs {
+        let name = format!("{:?} (+{} / -{})", diff.item_type, diff.added.len(), diff.removed.len());
+        embed = embed.field(name, describe_diff(diff), false);
+    }
+
+    ctx.send(poise::CreateReply::default().embed(embed)).await?;
+    Ok(())
+}
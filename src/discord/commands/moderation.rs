@@ -0,0 +1,185 @@
+use crate::auth_manager::OAuthManager;
+use crate::discord::bot::{CommandError, Context};
+use crate::discord::permissions::{author_role, is_moderator, user_role};
+use crate::user;
+use crate::user::User;
+use crate::util::UuidString;
+use chrono::{TimeDelta, Utc};
+use poise::serenity_prelude as serenity;
+use poise::serenity_prelude::colours::roles::{GREEN, RED};
+
+/// The most ban records listed by [bans], newest first.
+const MAX_LISTED: usize = 10;
+
+/// Parses durations like `30m`, `12h`, `7d` or `2w`.
+fn parse_duration(duration: &str) -> Option<TimeDelta> {
+    let duration = duration.trim();
+    let unit = duration.chars().last()?;
+    let amount = duration[..duration.len() - unit.len_utf8()].parse::<i64>().ok()
+        .filter(|amount| *amount > 0)?;
+    match unit.to_ascii_lowercase() {
+        'm' => TimeDelta::try_minutes(amount),
+        'h' => TimeDelta::try_hours(amount),
+        'd' => TimeDelta::try_days(amount),
+        'w' => TimeDelta::try_weeks(amount),
+        _ => None,
+    }
+}
+
+/// Replies with an error and returns [None] if the Discord user doesn't have an account.
+async fn find_account(ctx: Context<'_>, member: &serenity::User) -> Result<Option<User>, CommandError> {
+    match user::get_user_by_discord_id(&ctx.data().global_state.mongo, member.id.into()).await {
+        Ok(Some(val)) => Ok(Some(val)),
+        Ok(None) => {
+            ctx.reply(format!("{} doesn't have an account.", member.name)).await?;
+            Ok(None)
+        }
+        Err(e) => {
+            ctx.reply(format!("Failed to get the account: {}", e)).await?;
+            Ok(None)
+        }
+    }
+}
+
+/// Bans a user and signs them out everywhere. Leave the duration out for a permanent ban.
+#[poise::command(slash_command, prefix_command, check = "is_moderator")]
+pub async fn ban(
+    ctx: Context<'_>,
+    #[description = "The user to ban"] user: serenity::User,
+    #[description = "Why they're being banned"] reason: String,
+    #[description = "How long the ban lasts, like 12h, 7d or 2w"] duration: Option<String>,
+) -> Result<(), CommandError> {
+    let expires_at = match duration.as_deref().map(parse_duration) {
+        Some(Some(duration)) => Some(Utc::now() + duration),
+        Some(None) => {
+            ctx.reply("Durations are a number followed by m, h, d or w, like 12h or 7d.").await?;
+            return Ok(());
+        }
+        None => None,
+    };
+    // Moderators can't ban each other or anyone above them.
+    let author = author_role(ctx).await?;
+    if user_role(ctx, &user).await? >= author {
+        ctx.reply(format!("You can only ban users below {}.", author)).await?;
+        return Ok(());
+    }
+    let Some(account) = find_account(ctx, &user).await? else {
+        return Ok(());
+    };
+
+    let state = &ctx.data().global_state;
+    let banned = match user::ban_user(&state.mongo, &account, ctx.author().id.into(), reason, expires_at).await {
+        Ok(val) => val,
+        Err(e) => {
+            ctx.reply(format!("Failed to ban {}: {}", account.display_name, e)).await?;
+            return Ok(());
+        }
+    };
+    // Banned players lose presence and messaging straight away, even if their tokens can't be
+    // killed.
+    state.xmpp.close(&banned.account_id.to_stripped_string(), None);
+    if let Err(e) = OAuthManager::kill_user_tokens(&state.mongo, &banned).await {
+        ctx.reply(format!("{} is banned, but signing them out failed: {}", banned.display_name, e)).await?;
+        return Ok(());
+    }
+
+    let until = match expires_at {
+        Some(expires_at) => format!("<t:{}:f>", expires_at.timestamp()),
+        None => "forever".to_string(),
+    };
+    ctx.send(
+        poise::CreateReply::default().embed(
+            serenity::CreateEmbed::default()
+                .title("User Banned")
+                .description(format!(
+                    "{name} is banned until {until}.\nReason: {reason}",
+                    name = banned.display_name,
+                    until = until,
+                    reason = banned.active_ban().map(|ban| ban.reason.as_str()).unwrap_or_default(),
+                ))
+                .color(RED),
+        ),
+    ).await?;
+    Ok(())
+}
+
+/// Lifts a user's ban early.
+#[poise::command(slash_command, prefix_command, check = "is_moderator")]
+pub async fn unban(
+    ctx: Context<'_>,
+    #[description = "The user to unban"] user: serenity::User,
+) -> Result<(), CommandError> {
+    let Some(account) = find_account(ctx, &user).await? else {
+        return Ok(());
+    };
+
+    match user::unban_user(&ctx.data().global_state.mongo, &account, ctx.author().id.into()).await {
+        Ok(val) => {
+            ctx.send(
+                poise::CreateReply::default().embed(
+                    serenity::CreateEmbed::default()
+                        .title("User Unbanned")
+                        .description(format!("{name} can sign in again.", name = val.display_name))
+                        .color(GREEN),
+                ),
+            ).await?;
+            Ok(())
+        }
+        Err(e) => {
+            ctx.reply(format!("Failed to unban {}: {}", account.display_name, e)).await?;
+            Ok(())
+        }
+    }
+}
+
+/// Lists a user's bans, newest first.
+#[poise::command(slash_command, prefix_command, check = "is_moderator")]
+pub async fn bans(
+    ctx: Context<'_>,
+    #[description = "The user to list the bans of"] user: serenity::User,
+) -> Result<(), CommandError> {
+    let Some(account) = find_account(ctx, &user).await? else {
+        return Ok(());
+    };
+    if account.ban_history.is_empty() {
+        ctx.reply(format!("{} has never been banned.", account.display_name)).await?;
+        return Ok(());
+    }
+
+    let mut embed = serenity::CreateEmbed::default()
+        .title(format!("Bans for {}", account.display_name))
+        .color(if account.active_ban().is_some() { RED } else { GREEN });
+    for record in account.ban_history.iter().rev().take(MAX_LISTED) {
+        let status = if record.is_active() {
+            "Active".to_string()
+        } else if let Some(lifted_at) = record.lifted_at {
+            format!("Lifted <t:{}:R> by <@{}>", lifted_at.timestamp(), record.lifted_by.unwrap_or_default())
+        } else {
+            "Expired".to_string()
+        };
+        let until = match record.expires_at {
+            Some(expires_at) => format!("<t:{}:f>", expires_at.timestamp()),
+            None => "forever".to_string(),
+        };
+        embed = embed.field(
+            record.issued_at.format("%Y-%m-%d %H:%M UTC").to_string(),
+            format!(
+                "Reason: {reason}\nIssued by: <@{issuer}>\nUntil: {until}\nStatus: {status}",
+                reason = record.reason,
+                issuer = record.issued_by,
+                until = until,
+                status = status,
+            ),
+            false,
+        );
+    }
+    if account.ban_history.len() > MAX_LISTED {
+        embed = embed.footer(serenity::CreateEmbedFooter::new(format!(
+            "{} older bans not shown",
+            account.ban_history.len() - MAX_LISTED,
+        )));
+    }
+
+    ctx.send(poise::CreateReply::default().embed(embed)).await?;
+    Ok(())
+}
@@ -0,0 +1,120 @@
+use crate::discord::bot::{CommandError, Context};
+use crate::discord::permissions::{author_role, is_admin};
+use crate::role;
+use crate::role::Role;
+use poise::serenity_prelude as serenity;
+use poise::serenity_prelude::colours::roles::GREEN;
+
+/// Makes sure the author outranks both the target's current role and the role being given, so
+/// nobody can promote someone to their own level or demote a peer.
+async fn can_manage(ctx: Context<'_>, target: &serenity::User, role: Role) -> Result<bool, CommandError> {
+    let author = author_role(ctx).await?;
+    let current = role::get_role(&ctx.data().global_state.mongo, target.id.into()).await?;
+    if current >= author || role >= author {
+        ctx.reply(format!("You can only manage roles below {}.", author)).await?;
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+#[poise::command(slash_command, prefix_command, subcommands("grant", "revoke", "show"), subcommand_required)]
+pub async fn role(_ctx: Context<'_>) -> Result<(), CommandError> {
+    Ok(())
+}
+
+/// Gives a user a role, replacing the one they were granted before.
+#[poise::command(slash_command, prefix_command, check = "is_admin")]
+pub async fn grant(
+    ctx: Context<'_>,
+    #[description = "The user to give the role to"] user: serenity::User,
+    #[description = "The role to give"] role: Role,
+) -> Result<(), CommandError> {
+    if matches!(role, Role::User | Role::Owner) {
+        ctx.reply("Only moderator and admin can be granted. Owners are set with OWNER_IDS.").await?;
+        return Ok(());
+    }
+    if !can_manage(ctx, &user, role).await? {
+        return Ok(());
+    }
+
+    match role::grant_role(&ctx.data().global_state.mongo, user.id.into(), role, ctx.author().id.into()).await {
+        Ok(()) => {
+            ctx.send(
+                poise::CreateReply::default().embed(
+                    serenity::CreateEmbed::default()
+                        .title("Role Granted")
+                        .description(format!("<@{id}> now has the {role} role.", id = user.id, role = role))
+                        .color(GREEN),
+                ),
+            ).await?;
+            Ok(())
+        }
+        Err(e) => {
+            ctx.reply(format!("Failed to grant the role: {}", e)).await?;
+            Ok(())
+        }
+    }
+}
+
+/// Takes away a user's granted role. Roles from Discord roles have to be removed in Discord.
+#[poise::command(slash_command, prefix_command, check = "is_admin")]
+pub async fn revoke(
+    ctx: Context<'_>,
+    #[description = "The user to take the role from"] user: serenity::User,
+) -> Result<(), CommandError> {
+    if !can_manage(ctx, &user, Role::User).await? {
+        return Ok(());
+    }
+
+    match role::revoke_role(&ctx.data().global_state.mongo, user.id.into()).await {
+        Ok(true) => {
+            ctx.send(
+                poise::CreateReply::default().embed(
+                    serenity::CreateEmbed::default()
+                        .title("Role Revoked")
+                        .description(format!("<@{id}> now has the {role} role.", id = user.id, role = Role::User))
+                        .color(GREEN),
+                ),
+            ).await?;
+            Ok(())
+        }
+        Ok(false) => {
+            ctx.reply(format!("{} doesn't have a granted role.", user.name)).await?;
+            Ok(())
+        }
+        Err(e) => {
+            ctx.reply(format!("Failed to revoke the role: {}", e)).await?;
+            Ok(())
+        }
+    }
+}
+
+/// Shows the role a user was granted.
+#[poise::command(slash_command, prefix_command, check = "is_admin")]
+pub async fn show(
+    ctx: Context<'_>,
+    #[description = "The user to show the role of"] user: serenity::User,
+) -> Result<(), CommandError> {
+    let mongo = &ctx.data().global_state.mongo;
+    let description = match role::get_grant(mongo, user.id.into()).await? {
+        _ if role::owner_ids().contains(&user.id.get()) => format!("<@{}> has the {} role.", user.id, Role::Owner),
+        Some(grant) => format!(
+            "<@{id}> was given the {role} role by <@{by}> <t:{at}:R>.",
+            id = user.id,
+            role = grant.role,
+            by = grant.granted_by,
+            at = grant.granted_at.timestamp(),
+        ),
+        None => format!("<@{}> doesn't have a granted role.", user.id),
+    };
+
+    ctx.send(
+        poise::CreateReply::default().embed(
+            serenity::CreateEmbed::default()
+                .title("Role")
+                .description(description)
+                .color(GREEN),
+        ),
+    ).await?;
+    Ok(())
+}
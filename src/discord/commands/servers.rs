@@ -0,0 +1,50 @@
+use crate::discord::bot::{CommandError, Context};
+use crate::discord::permissions::is_owner;
+use poise::serenity_prelude as serenity;
+use poise::serenity_prelude::colours::roles::BLUE;
+
+/// The most servers listed before the rest are summarized.
+const MAX_LISTED: usize = 20;
+
+/// Lists every game server players can currently be matched into.
+#[poise::command(slash_command, prefix_command, check = "is_owner", ephemeral)]
+pub async fn servers(
+    ctx: Context<'_>,
+) -> Result<(), CommandError> {
+    let mut servers = ctx.data().global_state.servers.servers();
+    servers.sort_by(|a, b| (&a.region, &a.playlist, &a.id).cmp(&(&b.region, &b.playlist, &b.id)));
+
+    let mut lines = servers.iter().take(MAX_LISTED).map(|server| {
+        let heartbeat = match server.last_heartbeat {
+            Some(heartbeat) => format!("heartbeat <t:{}:R>", heartbeat.timestamp()),
+            None => "static".to_string(),
+        };
+        format!(
+            "`{}` {}:{} · {} · {} · {}/{} players · {}",
+            server.id,
+            server.ip,
+            server.port,
+            server.region,
+            server.playlist,
+            server.max_players - server.open_slots(),
+            server.max_players,
+            heartbeat,
+        )
+    }).collect::<Vec<String>>();
+    if servers.len() > MAX_LISTED {
+        lines.push(format!("...and {} more", servers.len() - MAX_LISTED));
+    }
+
+    let description = if lines.is_empty() {
+        "No game servers are registered.".to_string()
+    } else {
+        lines.join("\n")
+    };
+    ctx.send(poise::CreateReply::default().embed(
+        serenity::CreateEmbed::default()
+            .title(format!("Game Servers ({})", servers.len()))
+            .description(description)
+            .color(BLUE),
+    )).await?;
+    Ok(())
+}
@@ -0,0 +1,32 @@
+use crate::athena::storefront;
+use crate::athena::storefront::DAILY_STOREFRONT;
+use crate::discord::bot::{CommandError, Context};
+use poise::serenity_prelude as serenity;
+use poise::serenity_prelude::colours::branding::YELLOW;
+
+/// Shows what the item shop is selling right now and when each section rotates.
+#[poise::command(slash_command, prefix_command)]
+pub async fn shop(
+    ctx: Context<'_>,
+) -> Result<(), CommandError> {
+    let state = &ctx.data().global_state;
+    let shop = storefront::current_shop(&state.shop_config, &state.item_manager.load());
+
+    let mut embed = serenity::CreateEmbed::default()
+        .title("Item Shop")
+        .color(YELLOW);
+    for section in &shop.sections {
+        let name = if section.storefront == DAILY_STOREFRONT { "Daily" } else { "Featured" };
+        let mut lines = section.entries.iter()
+            .map(|entry| format!("`{}` ({:?}) · {} V-Bucks", entry.template_id, entry.item_type, entry.price))
+            .collect::<Vec<String>>();
+        if lines.is_empty() {
+            lines.push("Nothing on sale.".to_string());
+        }
+        lines.push(format!("Rotates <t:{}:R>", section.expires_at.timestamp()));
+        embed = embed.field(name, lines.join("\n"), false);
+    }
+
+    ctx.send(poise::CreateReply::default().embed(embed)).await?;
+    Ok(())
+}
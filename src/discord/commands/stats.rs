@@ -0,0 +1,57 @@
+use crate::discord::bot::{CommandError, Context};
+use crate::discord::commands::friend::find_target;
+use crate::stats;
+use crate::user;
+use crate::util::UuidString;
+use poise::serenity_prelude as serenity;
+use poise::serenity_prelude::colours::roles::BLUE;
+
+/// Shows a player's wins, K/D and matches, across every playlist unless one is supplied.
+#[poise::command(slash_command, prefix_command)]
+pub async fn stats(
+    ctx: Context<'_>,
+    #[description = "A mention or display name, yourself if empty"] player: Option<String>,
+    #[description = "Only count this playlist, like Playlist_DefaultSolo"] playlist: Option<String>,
+    #[description = "Only count this season"] season: Option<u32>,
+) -> Result<(), CommandError> {
+    let account = match player {
+        Some(player) => match find_target(ctx, &player).await? {
+            Some(val) => val,
+            None => return Ok(()),
+        },
+        None => match user::get_user_by_discord_id(&ctx.data().global_state.mongo, ctx.author().id.into()).await? {
+            Some(val) => val,
+            None => {
+                ctx.reply("You don't have an account yet, use /register to create one.").await?;
+                return Ok(());
+            }
+        },
+    };
+
+    let mut player_stats = stats::get_stats(&ctx.data().global_state.mongo, &account.account_id.to_stripped_string(), season).await?;
+    if let Some(playlist) = &playlist {
+        player_stats.retain(|stats| stats.playlist.eq_ignore_ascii_case(playlist));
+    }
+    let summary = stats::summarize(&player_stats);
+
+    let scope = match (&playlist, season) {
+        (Some(playlist), Some(season)) => format!("{} in season {}", playlist, season),
+        (Some(playlist), None) => playlist.clone(),
+        (None, Some(season)) => format!("Season {}", season),
+        (None, None) => "Lifetime".to_string(),
+    };
+    ctx.send(poise::CreateReply::default().embed(
+        serenity::CreateEmbed::default()
+            .title(format!("{}'s Stats", account.display_name))
+            .description(scope)
+            .field("Wins", summary.wins.to_string(), true)
+            .field("Win Rate", format!("{:.1}%", summary.win_rate()), true)
+            .field("Matches", summary.matches_played.to_string(), true)
+            .field("Kills", summary.kills.to_string(), true)
+            .field("K/D", format!("{:.2}", summary.kd()), true)
+            .field("Top 10", summary.top10.to_string(), true)
+            .field("Minutes Played", summary.minutes_played.to_string(), true)
+            .color(BLUE),
+    )).await?;
+    Ok(())
+}
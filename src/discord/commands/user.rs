@@ -1,19 +1,20 @@
 use poise::serenity_prelude as serenity;
-use poise::serenity_prelude::colours::roles::GREEN;
+use poise::serenity_prelude::colours::roles::{GREEN, RED};
+use crate::auth_manager::OAuthManager;
 use crate::discord::bot::{CommandError, Context};
+use crate::discord::permissions::is_admin;
 use crate::user;
+use crate::util::UuidString;
+use crate::xmpp::session::close_revoked;
+use log::error;
 
-#[poise::command(slash_command, prefix_command, owners_only = true)]
+#[poise::command(slash_command, prefix_command, check = "is_admin")]
 pub async fn create_user(
     ctx: Context<'_>,
     user: serenity::User,
     display_name: Option<String>,
 ) -> Result<(), CommandError> {
-    let display_name = if display_name.is_some() {
-        display_name.unwrap()
-    } else {
-        user.name
-    };
+    let display_name = display_name.unwrap_or(user.name);
     match user::create_user(&ctx.data().global_state.mongo, user.id.into(), display_name.to_string()).await {
         Ok(val) => {
             ctx.send(
@@ -32,3 +33,162 @@ pub async fn create_user(
         }
     }
 }
+
+/// Only lets members with the role in `REGISTER_ROLE_ID` register, if it is set.
+async fn can_register(ctx: Context<'_>) -> Result<bool, CommandError> {
+    let Ok(role_id) = std::env::var("REGISTER_ROLE_ID") else {
+        return Ok(true);
+    };
+    let role_id = serenity::RoleId::new(role_id.parse()?);
+
+    let allowed = match ctx.author_member().await {
+        Some(member) => member.roles.contains(&role_id),
+        None => false,
+    };
+    if !allowed {
+        ctx.send(
+            poise::CreateReply::default()
+                .content(format!("You need the <@&{}> role to register.", role_id))
+                .ephemeral(true),
+        ).await?;
+    }
+    Ok(allowed)
+}
+
+/// Creates a Glyph account for the Discord user running the command.
+#[poise::command(slash_command, ephemeral, check = "can_register")]
+pub async fn register(
+    ctx: Context<'_>,
+    #[description = "The name other players will see"] display_name: String,
+) -> Result<(), CommandError> {
+    let state = &ctx.data().global_state;
+    match user::create_user(&state.mongo, ctx.author().id.into(), display_name).await {
+        Ok(val) => {
+            ctx.send(
+                poise::CreateReply::default().embed(
+                    serenity::CreateEmbed::default()
+                        .title("Registered")
+                        .description(format!("Welcome, {name}! Use /login to get a code to sign in with.", name = val.display_name))
+                        .color(GREEN),
+                ),
+            ).await?;
+            Ok(())
+        }
+        Err(e) => {
+            ctx.reply(format!("Failed to register: {}", e)).await?;
+            Ok(())
+        }
+    }
+}
+
+/// Replies privately with an exchange code and the launch arguments to sign in with it.
+#[poise::command(slash_command, ephemeral)]
+pub async fn login(
+    ctx: Context<'_>,
+) -> Result<(), CommandError> {
+    let state = &ctx.data().global_state;
+    let user = match user::get_user_by_discord_id(&state.mongo, ctx.author().id.into()).await {
+        Ok(Some(val)) => val,
+        Ok(None) => {
+            ctx.reply("You don't have an account yet, use /register to create one.").await?;
+            return Ok(());
+        }
+        Err(e) => {
+            ctx.reply(format!("Failed to get your account: {}", e)).await?;
+            return Ok(());
+        }
+    };
+
+    if let Some(ban) = user.active_ban() {
+        let until = match ban.expires_at {
+            Some(expires_at) => format!("<t:{}:f>", expires_at.timestamp()),
+            None => "forever".to_string(),
+        };
+        ctx.send(
+            poise::CreateReply::default().embed(
+                serenity::CreateEmbed::default()
+                    .title("Account Banned")
+                    .description(format!(
+                        "You can't sign in because your account is banned.\n\nReason: {reason}\nUntil: {until}",
+                        reason = ban.reason,
+                        until = until,
+                    ))
+                    .color(RED),
+            ),
+        ).await?;
+        return Ok(());
+    }
+
+    let code = match state.auth_manager.make_exchange_code(&state.mongo, &user, None, None).await {
+        Ok(val) => val,
+        Err(e) => {
+            ctx.reply(format!("Failed to create an exchange code: {}", e)).await?;
+            return Ok(());
+        }
+    };
+
+    ctx.send(
+        poise::CreateReply::default().embed(
+            serenity::CreateEmbed::default()
+                .title("Exchange Code")
+                .description(format!(
+                    "Signing in as {name}. This code can only be used once and expires <t:{expires}:R>.\n\nCode:\n```\n{code}\n```\nLaunch arguments:\n```\n-AUTH_TYPE=exchangecode -AUTH_PASSWORD={code}\n```",
+                    name = user.display_name,
+                    expires = code.expires_at.timestamp(),
+                    code = code.token,
+                ))
+                .color(GREEN),
+        ),
+    ).await?;
+    Ok(())
+}
+
+/// Changes your display name. Signs you out everywhere so the new name shows up in game.
+#[poise::command(slash_command, ephemeral)]
+pub async fn rename(
+    ctx: Context<'_>,
+    #[description = "Your new display name"] display_name: String,
+) -> Result<(), CommandError> {
+    let state = &ctx.data().global_state;
+    let user = match user::get_user_by_discord_id(&state.mongo, ctx.author().id.into()).await {
+        Ok(Some(val)) => val,
+        Ok(None) => {
+            ctx.reply("You don't have an account yet, use /register to create one.").await?;
+            return Ok(());
+        }
+        Err(e) => {
+            ctx.reply(format!("Failed to get your account: {}", e)).await?;
+            return Ok(());
+        }
+    };
+
+    let updated = match user::change_display_name(&state.mongo, &user, display_name).await {
+        Ok(val) => val,
+        Err(e) => {
+            ctx.reply(format!("Failed to change your display name: {}", e)).await?;
+            return Ok(());
+        }
+    };
+    if updated.display_name == user.display_name {
+        ctx.reply(format!("Your display name is already {}.", user.display_name)).await?;
+        return Ok(());
+    }
+    if let Err(e) = OAuthManager::kill_user_tokens(&state.mongo, &updated).await {
+        error!("Failed to kill tokens after a display name change: {}", e);
+    }
+    close_revoked(state, &updated.account_id.to_stripped_string()).await;
+
+    ctx.send(
+        poise::CreateReply::default().embed(
+            serenity::CreateEmbed::default()
+                .title("Display Name Changed")
+                .description(format!(
+                    "{old} is now {new}. You've been signed out, use /login to sign back in.",
+                    old = user.display_name,
+                    new = updated.display_name,
+                ))
+                .color(GREEN),
+        ),
+    ).await?;
+    Ok(())
+}
@@ -0,0 +1,61 @@
+use crate::discord::bot::{CommandError, Context};
+use crate::role;
+use crate::role::Role;
+use poise::serenity_prelude as serenity;
+
+/// Returns the highest role the author of the command has, from `OWNER_IDS`, the granted roles
+/// and, when the command is run in a guild, the Discord roles mapped with `ADMIN_ROLE_ID` and
+/// `MODERATOR_ROLE_ID`.
+pub async fn author_role(ctx: Context<'_>) -> Result<Role, CommandError> {
+    let mut highest = role::get_role(&ctx.data().global_state.mongo, ctx.author().id.into()).await?;
+    if let Some(member) = ctx.author_member().await {
+        for (role_id, role) in role::guild_role_mapping() {
+            if member.roles.contains(&serenity::RoleId::new(role_id)) {
+                highest = highest.max(role);
+            }
+        }
+    }
+    Ok(highest)
+}
+
+/// Returns the highest role another user has, the same way [author_role] does. Their Discord
+/// roles only count when the command is run in a guild they are in.
+pub async fn user_role(ctx: Context<'_>, user: &serenity::User) -> Result<Role, CommandError> {
+    let mut highest = role::get_role(&ctx.data().global_state.mongo, user.id.into()).await?;
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(highest);
+    };
+    if let Ok(member) = guild_id.member(ctx, user.id).await {
+        for (role_id, role) in role::guild_role_mapping() {
+            if member.roles.contains(&serenity::RoleId::new(role_id)) {
+                highest = highest.max(role);
+            }
+        }
+    }
+    Ok(highest)
+}
+
+/// Checks that the author has at least the supplied role, and tells them if they don't.
+async fn require_role(ctx: Context<'_>, required: Role) -> Result<bool, CommandError> {
+    let allowed = author_role(ctx).await? >= required;
+    if !allowed {
+        ctx.send(
+            poise::CreateReply::default()
+                .content(format!("You need the {} role or higher to use this command.", required))
+                .ephemeral(true),
+        ).await?;
+    }
+    Ok(allowed)
+}
+
+pub async fn is_moderator(ctx: Context<'_>) -> Result<bool, CommandError> {
+    require_role(ctx, Role::Moderator).await
+}
+
+pub async fn is_admin(ctx: Context<'_>) -> Result<bool, CommandError> {
+    require_role(ctx, Role::Admin).await
+}
+
+pub async fn is_owner(ctx: Context<'_>) -> Result<bool, CommandError> {
+    require_role(ctx, Role::Owner).await
+}
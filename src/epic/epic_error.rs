@@ -1,4 +1,5 @@
 use axum::http::{HeaderMap, StatusCode};
+use axum::Json;
 
 #[derive(serde::Serialize, Debug)]
 pub struct ErrorResponse {
@@ -9,19 +10,30 @@ pub struct ErrorResponse {
     #[serde(rename = "messageVars")]
     message_vars: Vec<String>,
     #[serde(rename = "numericErrorCode")]
-    numeric_error_code: i16,
+    numeric_error_code: i32,
     #[serde(rename = "originatingService")]
     originating_service: String,
     intent: String,
 }
 
+/// The status, headers, and body of an Epic style error, as returned by [make_epic_err].
+pub type EpicError = (StatusCode, Option<HeaderMap>, ErrorResponse);
+
+/// An [EpicError] with its body wrapped in [Json] so it can be returned from a handler. The body
+/// is boxed to keep results that use this as their error small.
+pub type EpicErrorResponse = (StatusCode, Option<HeaderMap>, Json<Box<ErrorResponse>>);
+
+pub fn to_response(err: EpicError) -> EpicErrorResponse {
+    (err.0, err.1, Json(Box::new(err.2)))
+}
+
 pub fn make_epic_err(
     error_code: &str,
     error_msg: &str,
     message_vars: &[String],
-    numeric_error_code: i16,
+    numeric_error_code: i32,
     status_code: StatusCode,
-) -> (StatusCode, Option<HeaderMap>, ErrorResponse) {
+) -> EpicError {
     let mut headers = HeaderMap::new();
     headers.insert("X-Epic-Error-Name", error_code.parse().unwrap());
     headers.insert("X-Epic-Error-Code", numeric_error_code.to_string().parse().unwrap());
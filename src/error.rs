@@ -1,3 +1,4 @@
+use crate::athena::items::ItemType;
 use hmac::digest::InvalidLength;
 use thiserror::Error;
 
@@ -11,6 +12,68 @@ pub enum Error {
     InvalidKeyLength(#[from] InvalidLength),
     #[error("Failed to sign a key: {:?}", .0)]
     SignFailed(#[from] jwt::Error),
+    #[error("Signing key {} is malformed", .0)]
+    MalformedSigningKey(String),
     #[error("Invalid Authorization header")]
     InvalidAuthorizationHeader,
+    #[error("Token signature or claims are invalid")]
+    InvalidToken,
+    #[error("Token has expired")]
+    TokenExpired,
+    #[error("Display name {}", .0)]
+    InvalidDisplayName(String),
+    #[error("The display name {} is already taken", .0)]
+    DisplayNameTaken(String),
+    #[error("This Discord account already has an account")]
+    DiscordAccountExists,
+    #[error("The display name can't be changed again until {}", .0)]
+    DisplayNameCooldown(chrono::DateTime<chrono::Utc>),
+    #[error("User not found")]
+    UserNotFound,
+    #[error("User is already banned")]
+    AlreadyBanned,
+    #[error("User isn't banned")]
+    NotBanned,
+    #[error("You can't add yourself as a friend")]
+    CannotFriendSelf,
+    #[error("You're already friends")]
+    AlreadyFriends,
+    #[error("A friend request was already sent")]
+    FriendRequestAlreadySent,
+    #[error("One of you has blocked the other")]
+    FriendshipBlocked,
+    #[error("Friendship not found")]
+    FriendshipNotFound,
+    #[error("The limit of {} friends has been reached", .0)]
+    FriendLimitReached(u64),
+    #[error("The limit of {} pending friend requests has been reached", .0)]
+    FriendRequestLimitReached(u64),
+    #[error("Party {} not found", .0)]
+    PartyNotFound(String),
+    #[error("{} isn't a member of the party", .0)]
+    PartyMemberNotFound(String),
+    #[error("Only the party captain can do that")]
+    NotPartyCaptain,
+    #[error("The party is full")]
+    PartyFull,
+    #[error("The party can only be joined with an invite")]
+    PartyJoinForbidden,
+    #[error("Party invite not found")]
+    PartyInviteNotFound,
+    #[error("Party ping not found")]
+    PartyPingNotFound,
+    #[error("Party revision {} is newer than {}", .0, .1)]
+    StalePartyRevision(i64, i64),
+    #[error("Failed to read item catalogue {}: {}", .0, .1)]
+    ItemFileRead(String, std::io::Error),
+    #[error("Item catalogue {} is malformed: {}", .0, .1)]
+    MalformedItemFile(String, serde_json::Error),
+    #[error("Item {} is listed as both {:?} and {:?}", .0, .1, .2)]
+    ConflictingItemType(String, ItemType, ItemType),
+    #[error("Failed to read shop config {}: {}", .0, .1)]
+    ShopConfigRead(String, std::io::Error),
+    #[error("Shop config {} is malformed: {}", .0, .1)]
+    MalformedShopConfig(String, serde_json::Error),
+    #[error("Profile {} kept being changed by other requests", .0)]
+    ProfileConflict(String),
 }
@@ -0,0 +1,314 @@
+use crate::error;
+use crate::error::Error;
+use crate::mongo::{is_duplicate_key, GlyphMongo, FRIENDS_COLL, USER_DB};
+use crate::user;
+use crate::util::UuidString;
+use crate::xmpp::hub::XmppHub;
+use bson::{doc, Document};
+use chrono::{DateTime, Utc};
+use mongodb::options::{ReplaceOptions, ReturnDocument};
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use uuid::Uuid;
+
+/// The most accepted friends an account can have.
+pub const MAX_FRIENDS: u64 = 1000;
+/// The most pending requests an account can have in each direction.
+pub const MAX_PENDING_REQUESTS: u64 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FriendshipStatus {
+    Pending,
+    Accepted,
+    Blocked,
+}
+
+/// A relationship between two accounts. For pending requests and blocks `account_id` is the
+/// account that sent the request or blocked the other, accepted friendships keep whoever sent the
+/// original request there.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Friendship {
+    /// See [friendship_key] and [block_key]. Using the key as the ID stops two requests between
+    /// the same accounts from being stored at once.
+    #[serde(rename = "_id")]
+    pub(crate) id: String,
+    pub(crate) account_id: String,
+    pub(crate) friend_id: String,
+    pub(crate) status: FriendshipStatus,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub(crate) created: DateTime<Utc>,
+    #[serde(default, with = "bson::serde_helpers::chrono_datetime_as_bson_datetime_optional")]
+    pub(crate) accepted: Option<DateTime<Utc>>,
+}
+
+impl Friendship {
+    /// Returns the account on the other side of the relationship from the supplied one.
+    pub fn other(&self, account_id: &str) -> &str {
+        if self.account_id == account_id {
+            &self.friend_id
+        } else {
+            &self.account_id
+        }
+    }
+}
+
+/// What [send_friend_request] did, along with the stored friendship.
+pub enum FriendRequestOutcome {
+    Sent(Friendship),
+    /// The other account had already sent a request, so it was accepted instead.
+    Accepted(Friendship),
+}
+
+/// Every relationship an account has, split up the way the friends summary wants them.
+#[derive(Default)]
+pub struct FriendsSummary {
+    pub(crate) friends: Vec<Friendship>,
+    pub(crate) incoming: Vec<Friendship>,
+    pub(crate) outgoing: Vec<Friendship>,
+    pub(crate) blocklist: Vec<Friendship>,
+}
+
+/// Friend requests and friendships share one key per pair of accounts no matter who sent them.
+fn friendship_key(a: &str, b: &str) -> String {
+    if a < b {
+        format!("{}:{}", a, b)
+    } else {
+        format!("{}:{}", b, a)
+    }
+}
+
+/// Blocks are one sided, so both accounts can block each other at once.
+fn block_key(blocker: &str, blocked: &str) -> String {
+    format!("block:{}:{}", blocker, blocked)
+}
+
+async fn friends_collection(mongo: &GlyphMongo) -> Collection<Friendship> {
+    mongo.collection::<Friendship>(USER_DB, FRIENDS_COLL).await
+}
+
+async fn find_friendships(mongo: &GlyphMongo, filter: Document) -> error::Result<Vec<Friendship>> {
+    let mut cursor = friends_collection(mongo).await.find(filter).await?;
+    let mut found = vec![];
+    while cursor.advance().await? {
+        found.push(cursor.deserialize_current()?);
+    }
+    Ok(found)
+}
+
+async fn count_friendships(mongo: &GlyphMongo, filter: Document) -> error::Result<u64> {
+    Ok(friends_collection(mongo).await.count_documents(filter).await?)
+}
+
+fn friends_filter(account_id: &str) -> Document {
+    doc! {
+        "status": "accepted",
+        "$or": [{ "account_id": account_id }, { "friend_id": account_id }],
+    }
+}
+
+/// Returns true if either account has blocked the other.
+pub async fn is_blocked(mongo: &GlyphMongo, a: &Uuid, b: &Uuid) -> error::Result<bool> {
+    let (a, b) = (a.to_stripped_string(), b.to_stripped_string());
+    let filter = doc! { "_id": { "$in": [block_key(&a, &b), block_key(&b, &a)] } };
+    Ok(count_friendships(mongo, filter).await? > 0)
+}
+
+/// Checks that adding one more friend wouldn't put either account over [MAX_FRIENDS].
+async fn check_friend_limit(mongo: &GlyphMongo, account_ids: [&str; 2]) -> error::Result<()> {
+    for account_id in account_ids {
+        if count_friendships(mongo, friends_filter(account_id)).await? >= MAX_FRIENDS {
+            return Err(Error::FriendLimitReached(MAX_FRIENDS));
+        }
+    }
+    Ok(())
+}
+
+/// Sends a friend request from `account_id` to `friend_id`. If `friend_id` already sent one the
+/// other way, the two become friends instead.
+pub async fn send_friend_request(mongo: &GlyphMongo, account_id: &Uuid, friend_id: &Uuid) -> error::Result<FriendRequestOutcome> {
+    if account_id == friend_id {
+        return Err(Error::CannotFriendSelf);
+    }
+    if user::get_user(mongo, friend_id).await?.is_none() {
+        return Err(Error::UserNotFound);
+    }
+    if is_blocked(mongo, account_id, friend_id).await? {
+        return Err(Error::FriendshipBlocked);
+    }
+
+    let (account_id, friend_id) = (account_id.to_stripped_string(), friend_id.to_stripped_string());
+    let key = friendship_key(&account_id, &friend_id);
+    let collection = friends_collection(mongo).await;
+    if let Some(existing) = collection.find_one(doc! { "_id": &key }).await? {
+        return match existing.status {
+            FriendshipStatus::Accepted => Err(Error::AlreadyFriends),
+            FriendshipStatus::Pending if existing.account_id == account_id => Err(Error::FriendRequestAlreadySent),
+            _ => {
+                check_friend_limit(mongo, [&account_id, &friend_id]).await?;
+                let accepted = collection.find_one_and_update(
+                    doc! { "_id": &key, "status": "pending" },
+                    doc! { "$set": { "status": "accepted", "accepted": bson::DateTime::now() } },
+                ).return_document(ReturnDocument::After).await?;
+                accepted.map(FriendRequestOutcome::Accepted).ok_or(Error::FriendshipNotFound)
+            }
+        };
+    }
+
+    check_friend_limit(mongo, [&account_id, &friend_id]).await?;
+    let outgoing = count_friendships(mongo, doc! { "status": "pending", "account_id": &account_id }).await?;
+    let incoming = count_friendships(mongo, doc! { "status": "pending", "friend_id": &friend_id }).await?;
+    if outgoing >= MAX_PENDING_REQUESTS || incoming >= MAX_PENDING_REQUESTS {
+        return Err(Error::FriendRequestLimitReached(MAX_PENDING_REQUESTS));
+    }
+
+    let request = Friendship {
+        id: key,
+        account_id,
+        friend_id,
+        status: FriendshipStatus::Pending,
+        created: Utc::now(),
+        accepted: None,
+    };
+    match collection.insert_one(&request).await {
+        Ok(_) => Ok(FriendRequestOutcome::Sent(request)),
+        // The other account sent a request at the same moment.
+        Err(e) if is_duplicate_key(&e) => Err(Error::FriendRequestAlreadySent),
+        Err(e) => Err(Error::MongoError(e)),
+    }
+}
+
+/// Removes a friend, or declines or cancels a pending request between the two accounts. Returns
+/// the relationship that was removed.
+pub async fn remove_friend(mongo: &GlyphMongo, account_id: &Uuid, friend_id: &Uuid) -> error::Result<Friendship> {
+    let key = friendship_key(&account_id.to_stripped_string(), &friend_id.to_stripped_string());
+    let removed = friends_collection(mongo).await
+        .find_one_and_delete(doc! { "_id": key, "status": { "$in": ["pending", "accepted"] } })
+        .await?;
+    removed.ok_or(Error::FriendshipNotFound)
+}
+
+/// Blocks `blocked_id`, removing any friendship or pending request between the two accounts.
+/// Returns the relationship that was removed, if there was one.
+pub async fn block(mongo: &GlyphMongo, account_id: &Uuid, blocked_id: &Uuid) -> error::Result<Option<Friendship>> {
+    if account_id == blocked_id {
+        return Err(Error::CannotFriendSelf);
+    }
+    if user::get_user(mongo, blocked_id).await?.is_none() {
+        return Err(Error::UserNotFound);
+    }
+
+    let (account_id, blocked_id) = (account_id.to_stripped_string(), blocked_id.to_stripped_string());
+    let collection = friends_collection(mongo).await;
+    let removed = collection.find_one_and_delete(doc! { "_id": friendship_key(&account_id, &blocked_id) }).await?;
+
+    let key = block_key(&account_id, &blocked_id);
+    let block = Friendship {
+        id: key.clone(),
+        account_id,
+        friend_id: blocked_id,
+        status: FriendshipStatus::Blocked,
+        created: Utc::now(),
+        accepted: None,
+    };
+    collection.replace_one(doc! { "_id": key }, block)
+        .with_options(ReplaceOptions::builder().upsert(true).build())
+        .await?;
+    Ok(removed)
+}
+
+/// Returns true if `account_id` has blocked `blocked_id`, unlike [is_blocked] which checks both ways.
+pub async fn has_blocked(mongo: &GlyphMongo, account_id: &Uuid, blocked_id: &Uuid) -> error::Result<bool> {
+    let key = block_key(&account_id.to_stripped_string(), &blocked_id.to_stripped_string());
+    Ok(count_friendships(mongo, doc! { "_id": key }).await? > 0)
+}
+
+/// Returns false if the account wasn't blocked.
+pub async fn unblock(mongo: &GlyphMongo, account_id: &Uuid, blocked_id: &Uuid) -> error::Result<bool> {
+    let key = block_key(&account_id.to_stripped_string(), &blocked_id.to_stripped_string());
+    let result = friends_collection(mongo).await.delete_one(doc! { "_id": key }).await?;
+    Ok(result.deleted_count > 0)
+}
+
+pub async fn get_friends(mongo: &GlyphMongo, account_id: &Uuid) -> error::Result<Vec<Friendship>> {
+    find_friendships(mongo, friends_filter(&account_id.to_stripped_string())).await
+}
+
+pub async fn get_incoming(mongo: &GlyphMongo, account_id: &Uuid) -> error::Result<Vec<Friendship>> {
+    find_friendships(mongo, doc! { "status": "pending", "friend_id": account_id.to_stripped_string() }).await
+}
+
+pub async fn get_outgoing(mongo: &GlyphMongo, account_id: &Uuid) -> error::Result<Vec<Friendship>> {
+    find_friendships(mongo, doc! { "status": "pending", "account_id": account_id.to_stripped_string() }).await
+}
+
+pub async fn get_blocklist(mongo: &GlyphMongo, account_id: &Uuid) -> error::Result<Vec<Friendship>> {
+    find_friendships(mongo, doc! { "status": "blocked", "account_id": account_id.to_stripped_string() }).await
+}
+
+/// Loads every relationship the account is part of with a single query.
+pub async fn get_summary(mongo: &GlyphMongo, account_id: &Uuid) -> error::Result<FriendsSummary> {
+    let account_id = account_id.to_stripped_string();
+    let filter = doc! { "$or": [{ "account_id": &account_id }, { "friend_id": &account_id }] };
+
+    let mut summary = FriendsSummary::default();
+    for friendship in find_friendships(mongo, filter).await? {
+        let sent = friendship.account_id == account_id;
+        match friendship.status {
+            FriendshipStatus::Accepted => summary.friends.push(friendship),
+            FriendshipStatus::Pending if sent => summary.outgoing.push(friendship),
+            FriendshipStatus::Pending => summary.incoming.push(friendship),
+            FriendshipStatus::Blocked if sent => summary.blocklist.push(friendship),
+            // Nobody gets told who blocked them.
+            FriendshipStatus::Blocked => {}
+        }
+    }
+    Ok(summary)
+}
+
+/// Tells both accounts about a new friend request or friendship over XMPP.
+pub fn notify_friendship(xmpp: &XmppHub, friendship: &Friendship) {
+    let status = match friendship.status {
+        FriendshipStatus::Accepted => "ACCEPTED",
+        _ => "PENDING",
+    };
+    for (account_id, direction) in [(&friendship.account_id, "OUTBOUND"), (&friendship.friend_id, "INBOUND")] {
+        xmpp.notify(account_id, json!({
+            "type": "com.epicgames.friends.core.apiobjects.Friend",
+            "payload": {
+                "accountId": friendship.other(account_id),
+                "status": status,
+                "direction": direction,
+                "created": friendship.created.to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+                "favorite": false,
+            },
+        }));
+    }
+}
+
+/// Tells both accounts that a friendship or pending request between them is gone.
+pub fn notify_removal(xmpp: &XmppHub, friendship: &Friendship) {
+    for account_id in [&friendship.account_id, &friendship.friend_id] {
+        xmpp.notify(account_id, json!({
+            "type": "com.epicgames.friends.core.apiobjects.FriendRemoval",
+            "payload": {
+                "accountId": friendship.other(account_id),
+                "reason": "DELETED",
+            },
+        }));
+    }
+}
+
+/// Tells the blocking account its block list changed. The blocked account isn't told.
+pub fn notify_block(xmpp: &XmppHub, account_id: &Uuid, blocked_id: &Uuid, blocked: bool) {
+    let notification = if blocked {
+        "com.epicgames.friends.core.apiobjects.BlockListEntryAdded"
+    } else {
+        "com.epicgames.friends.core.apiobjects.BlockListEntryRemoved"
+    };
+    xmpp.notify(&account_id.to_stripped_string(), json!({
+        "type": notification,
+        "payload": { "accountId": blocked_id.to_stripped_string() },
+    }));
+}
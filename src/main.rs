@@ -1,19 +1,42 @@
 mod auth_manager;
 mod serializers;
+mod signing_key;
 mod mongo;
 mod error;
+mod friends;
+mod party;
+mod role;
+mod stats;
 mod user;
 mod util;
 
 mod athena {
-    mod items;
+    pub mod items;
+    pub mod locker;
+    pub mod matches;
+    pub mod storefront;
+}
+
+mod mcp {
+    pub mod commands;
+    pub mod defaults;
+    pub mod profile;
 }
 
 mod discord {
     pub mod bot;
+    pub mod permissions;
 
     pub mod commands {
+        pub mod auth;
+        pub mod friend;
+        pub mod items;
         pub mod misc;
+        pub mod moderation;
+        pub mod role;
+        pub mod servers;
+        pub mod shop;
+        pub mod stats;
         pub mod user;
     }
 }
@@ -22,25 +45,65 @@ mod epic {
     pub(crate) mod epic_error;
 }
 
+mod matchmaking {
+    pub mod matchmaker;
+    pub mod registry;
+}
+
+mod xmpp {
+    pub mod element;
+    pub mod hub;
+    pub mod session;
+}
+
 mod route {
     pub mod account {
+        pub mod public;
         pub mod auth;
     }
+    pub mod fortnite {
+        pub mod matchmaking;
+        pub mod profile;
+        pub mod storefront;
+    }
+    pub mod friends {
+        pub mod v1;
+    }
+    pub mod party {
+        pub mod v1;
+    }
+    pub mod stats {
+        pub mod v2;
+    }
+    pub mod bearer;
+    pub mod matchmaker;
     pub(crate) mod router;
+    pub mod servers;
+    pub mod xmpp;
 }
 
+use crate::athena::items::ItemManager;
+use crate::athena::storefront::ShopConfig;
 use crate::auth_manager::OAuthManager;
+use crate::matchmaking::registry::ServerRegistry;
 use crate::mongo::GlyphMongo;
+use crate::party::PartyManager;
+use crate::xmpp::hub::XmppHub;
+use arc_swap::ArcSwap;
 use std::sync::{Arc};
 use log::{error, info};
 use tokio::sync::oneshot;
 use tokio::sync::oneshot::Receiver;
-use uuid::Uuid;
 use route::router;
 
 pub struct GlyphState {
     mongo: GlyphMongo,
     auth_manager: OAuthManager,
+    item_manager: ArcSwap<ItemManager>,
+    shop_config: ShopConfig,
+    xmpp: XmppHub,
+    parties: PartyManager,
+    servers: ServerRegistry,
 }
 
 pub enum ChannelCommand {
@@ -51,27 +114,73 @@ pub enum ChannelCommand {
 
 #[tokio::main]
 async fn main() {
-    let deployment_id = Uuid::new_v4();
-    let signing_key = match OAuthManager::gen_signing_key(deployment_id.clone()) {
+    tracing_subscriber::fmt::init();
+    let mongo = GlyphMongo::new().await.unwrap();
+    if let Err(e) = user::create_indexes(&mongo).await {
+        error!("Unable to create user indexes: {}", e);
+        return;
+    }
+    if let Err(e) = role::create_indexes(&mongo).await {
+        error!("Unable to create role indexes: {}", e);
+        return;
+    }
+    if let Err(e) = stats::create_indexes(&mongo).await {
+        error!("Unable to create stats indexes: {}", e);
+        return;
+    }
+    let auth_manager = match OAuthManager::new(&mongo).await {
         Ok(val) => val,
         Err(e) => {
-            error!("Unable to generate JWT signing key: {}", e);
+            error!("Unable to load JWT signing keys: {}", e);
+            return;
+        }
+    };
+
+    let item_manager = match ItemManager::new() {
+        Ok(val) => val,
+        Err(e) => {
+            error!("Unable to load the item catalogue: {}", e);
+            return;
+        }
+    };
+
+    let shop_config = match ShopConfig::new() {
+        Ok(val) => val,
+        Err(e) => {
+            error!("Unable to load the shop config: {}", e);
+            return;
+        }
+    };
+
+    let parties = match PartyManager::new(&mongo).await {
+        Ok(val) => val,
+        Err(e) => {
+            error!("Unable to load persisted parties: {}", e);
             return;
         }
     };
 
     let shared_state = Arc::new(GlyphState {
-        mongo: GlyphMongo::new().await.unwrap(),
-        auth_manager: OAuthManager::new(signing_key),
+        mongo,
+        auth_manager,
+        item_manager: ArcSwap::from_pointee(item_manager),
+        shop_config,
+        xmpp: XmppHub::default(),
+        parties,
+        servers: ServerRegistry::from_env(),
     });
     let (tx, mut rx) = oneshot::channel::<ChannelCommand>();
     let token = tokio_util::sync::CancellationToken::new();
     let cloned_token = token.clone();
 
-    tracing_subscriber::fmt::init();
     let app = router::create_router(shared_state.clone());
     let listener = tokio::net::TcpListener::bind("0.0.0.0:5746").await.unwrap();
 
+    let eviction_state = shared_state.clone();
+    tokio::spawn(async move {
+        eviction_state.servers.run_eviction().await;
+    });
+
     tokio::spawn(async move {
         tokio::select! {
             _ = async {
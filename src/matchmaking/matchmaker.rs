@@ -0,0 +1,126 @@
+use crate::GlyphState;
+use axum::extract::ws::{Message, WebSocket};
+use log::info;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How long to wait between checks for a free server while queued.
+const QUEUE_POLL_INTERVAL: Duration = Duration::from_secs(3);
+/// How long to show the session assignment before telling the client to join.
+const JOIN_DELAY: Duration = Duration::from_secs(2);
+
+/// The claims of a signed matchmaking ticket.
+pub struct MatchmakingTicket {
+    pub(crate) ticket_id: String,
+    pub(crate) account_id: String,
+    /// Everyone matchmaking together, including [MatchmakingTicket::account_id].
+    pub(crate) party_player_ids: Vec<String>,
+    pub(crate) build_unique_id: String,
+    pub(crate) region: String,
+    pub(crate) playlist: String,
+}
+
+impl MatchmakingTicket {
+    /// Reads the ticket from verified claims, returning [None] if any are missing.
+    pub fn from_claims(claims: &BTreeMap<String, String>) -> Option<Self> {
+        Some(Self {
+            ticket_id: claims.get("jti")?.clone(),
+            account_id: claims.get("accountId")?.clone(),
+            party_player_ids: claims.get("partyPlayerIds")?.split(',').map(str::to_string).collect(),
+            build_unique_id: claims.get("buildUniqueId")?.clone(),
+            region: claims.get("region")?.clone(),
+            playlist: claims.get("playlist")?.clone(),
+        })
+    }
+}
+
+/// Splits a bucket ID, `buildUniqueId:0:region:playlist`, into the build, region and playlist.
+pub fn parse_bucket_id(bucket_id: &str) -> Option<(&str, &str, &str)> {
+    let parts = bucket_id.split(':').collect::<Vec<_>>();
+    match parts[..] {
+        [build, _, region, playlist, ..] if !region.is_empty() && !playlist.is_empty() => Some((build, region, playlist)),
+        _ => None,
+    }
+}
+
+/// Sends a message in the shape the client expects, returning false if the socket is closed.
+async fn send(socket: &mut WebSocket, name: &str, payload: Value) -> bool {
+    let message = json!({ "payload": payload, "name": name });
+    socket.send(Message::Text(message.to_string())).await.is_ok()
+}
+
+/// Walks the client through the matchmaking states, holding the ticket in the queue until a
+/// server in its region has room for the whole party.
+pub async fn run(state: Arc<GlyphState>, mut socket: WebSocket, ticket: MatchmakingTicket) {
+    let players = ticket.party_player_ids.len();
+    if !send(&mut socket, "StatusUpdate", json!({ "state": "Connecting" })).await {
+        return;
+    }
+    if !send(&mut socket, "StatusUpdate", json!({
+        "totalPlayers": players,
+        "connectedPlayers": players,
+        "state": "Waiting",
+    })).await {
+        return;
+    }
+
+    let (server, reserved) = {
+        let _queued = state.servers.enqueue();
+        loop {
+            if let Some(assigned) = state.servers.assign(&ticket.region, &ticket.playlist, &ticket.party_player_ids, &ticket.build_unique_id) {
+                break assigned;
+            }
+            if !send(&mut socket, "StatusUpdate", json!({
+                "ticketId": ticket.ticket_id,
+                "queuedPlayers": state.servers.queued(),
+                "estimatedWaitSec": 0,
+                "status": {},
+                "state": "Queued",
+            })).await {
+                return;
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep(QUEUE_POLL_INTERVAL) => {}
+                message = socket.recv() => match message {
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                    Some(Ok(_)) => {}
+                },
+            }
+        }
+    };
+    info!("Matched {} into session {} on {}:{}", ticket.account_id, server.session_id, server.ip, server.port);
+
+    let played = send(&mut socket, "StatusUpdate", json!({
+        "matchId": server.session_id,
+        "state": "SessionAssignment",
+    })).await && wait_for_join(&mut socket).await && send(&mut socket, "Play", json!({
+        "matchId": server.session_id,
+        "sessionId": server.session_id,
+        "joinDelaySec": 1,
+    })).await;
+    if !played {
+        // The client left before it was told to join, so nobody is coming to the slots this
+        // ticket reserved.
+        state.servers.release(&server.id, &reserved);
+        return;
+    }
+    let _ = socket.send(Message::Close(None)).await;
+}
+
+/// Waits for [JOIN_DELAY], returning false if the client closes the socket in the meantime.
+async fn wait_for_join(socket: &mut WebSocket) -> bool {
+    let delay = tokio::time::sleep(JOIN_DELAY);
+    tokio::pin!(delay);
+    loop {
+        tokio::select! {
+            _ = &mut delay => return true,
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return false,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
@@ -0,0 +1,314 @@
+use crate::util::UuidString;
+use chrono::{DateTime, TimeDelta, Utc};
+use log::{info, warn};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::RwLock;
+use std::time::Duration;
+use uuid::Uuid;
+
+const DEFAULT_MAX_PLAYERS: u32 = 100;
+/// How long a registered server can go without a heartbeat before it is evicted.
+const HEARTBEAT_TIMEOUT: TimeDelta = TimeDelta::seconds(60);
+const EVICTION_INTERVAL: Duration = Duration::from_secs(15);
+
+/// A dedicated server players can be sent to. Each server hosts one session at a time.
+#[derive(Clone)]
+pub struct GameServer {
+    pub(crate) id: String,
+    pub(crate) ip: String,
+    pub(crate) port: u16,
+    pub(crate) region: String,
+    pub(crate) playlist: String,
+    pub(crate) max_players: u32,
+    pub(crate) session_id: String,
+    /// Sent to the client so it can prove to the server that it was assigned to the session.
+    pub(crate) session_key: String,
+    /// The account ID of every player that was assigned to the session.
+    pub(crate) players: Vec<String>,
+    /// How many players the server last said were connected.
+    pub(crate) reported_players: u32,
+    /// When the server last sent a heartbeat, [None] for servers from `GAME_SERVERS` which are
+    /// never evicted.
+    pub(crate) last_heartbeat: Option<DateTime<Utc>>,
+    /// The client that registered the server, [None] for servers from `GAME_SERVERS`.
+    pub(crate) owner: Option<String>,
+}
+
+impl GameServer {
+    pub fn new(ip: &str, port: u16, region: &str, playlist: &str, max_players: u32) -> Self {
+        Self {
+            id: Uuid::new_v4().to_stripped_string(),
+            ip: ip.to_string(),
+            port,
+            region: region.to_string(),
+            playlist: playlist.to_string(),
+            max_players,
+            session_id: Uuid::new_v4().to_stripped_string(),
+            session_key: Uuid::new_v4().to_stripped_string(),
+            players: vec![],
+            reported_players: 0,
+            last_heartbeat: None,
+            owner: None,
+        }
+    }
+
+    /// Creates a server that registered itself through the server API and has to keep sending
+    /// heartbeats to stay registered. Only the client that registered it can manage it.
+    pub fn registered(owner: &str, ip: &str, port: u16, region: &str, playlist: &str, max_players: u32) -> Self {
+        Self {
+            last_heartbeat: Some(Utc::now()),
+            owner: Some(owner.to_string()),
+            ..Self::new(ip, port, region, playlist, max_players)
+        }
+    }
+
+    /// Slots are taken by players that were assigned or that the server says are connected,
+    /// whichever is more.
+    pub fn open_slots(&self) -> u32 {
+        let taken = (self.players.len() as u32).max(self.reported_players);
+        self.max_players.saturating_sub(taken)
+    }
+
+    /// How many of the accounts don't have a slot on the server yet.
+    fn unreserved(&self, account_ids: &[String]) -> usize {
+        account_ids.iter().filter(|account_id| !self.players.contains(account_id)).count()
+    }
+
+    /// Whether the server hosts the playlist in the region. Both are compared ignoring case
+    /// because the client doesn't always send them with the same casing.
+    fn serves(&self, region: &str, playlist: &str) -> bool {
+        self.region.eq_ignore_ascii_case(region) && self.playlist.eq_ignore_ascii_case(playlist)
+    }
+}
+
+/// Where a player was sent by the matchmaker.
+#[derive(Clone)]
+pub struct Assignment {
+    pub(crate) server_id: String,
+    /// The build the player's ticket was issued for, the client checks it against the session.
+    pub(crate) build_unique_id: String,
+}
+
+#[derive(Default)]
+struct Registry {
+    servers: HashMap<String, GameServer>,
+    assignments: HashMap<String, Assignment>,
+}
+
+/// Every dedicated server that can be matched into, and the session every player was assigned.
+#[derive(Default)]
+pub struct ServerRegistry {
+    registry: RwLock<Registry>,
+    queued: AtomicU32,
+}
+
+impl ServerRegistry {
+    /// Creates a registry with the servers from `GAME_SERVERS`, a comma separated list of
+    /// `ip:port:region:playlist` entries. Malformed entries are skipped.
+    pub fn from_env() -> Self {
+        let registry = Self::default();
+        let Ok(servers) = std::env::var("GAME_SERVERS") else {
+            return registry;
+        };
+
+        for entry in servers.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+            let parts = entry.split(':').collect::<Vec<_>>();
+            let [ip, port, region, playlist] = parts[..] else {
+                warn!("Skipping malformed game server entry {}", entry);
+                continue;
+            };
+            let Ok(port) = port.parse() else {
+                warn!("Skipping game server {} with an invalid port", entry);
+                continue;
+            };
+            registry.register(GameServer::new(ip, port, region, playlist, DEFAULT_MAX_PLAYERS));
+        }
+        info!("Loaded {} game servers", registry.servers().len());
+        registry
+    }
+
+    pub fn register(&self, server: GameServer) {
+        self.registry.write().unwrap().servers.insert(server.id.clone(), server);
+    }
+
+    /// Whether the client can manage the server, which is any server client for servers from
+    /// `GAME_SERVERS`. Returns [None] if the server isn't registered.
+    pub fn managed_by(&self, server_id: &str, client_id: &str) -> Option<bool> {
+        let registry = self.registry.read().unwrap();
+        let server = registry.servers.get(server_id)?;
+        Some(server.owner.as_ref().is_none_or(|owner| owner == client_id))
+    }
+
+    /// Removes a server along with the assignments to it, returning false if it wasn't registered.
+    pub fn deregister(&self, server_id: &str) -> bool {
+        let mut registry = self.registry.write().unwrap();
+        if registry.servers.remove(server_id).is_none() {
+            return false;
+        }
+        registry.assignments.retain(|_, assignment| assignment.server_id != server_id);
+        true
+    }
+
+    /// Records a heartbeat from a server along with its player count if it sent one. Returns
+    /// false if the server isn't registered, likely because it was evicted.
+    pub fn heartbeat(&self, server_id: &str, players: Option<u32>) -> bool {
+        let mut registry = self.registry.write().unwrap();
+        let Some(server) = registry.servers.get_mut(server_id) else {
+            return false;
+        };
+        if server.last_heartbeat.is_some() {
+            server.last_heartbeat = Some(Utc::now());
+        }
+        if let Some(players) = players {
+            server.reported_players = players;
+        }
+        true
+    }
+
+    /// Removes every registered server that missed its heartbeats, along with the assignments to
+    /// them, and returns how many there were.
+    pub fn evict_stale(&self) -> usize {
+        let cutoff = Utc::now() - HEARTBEAT_TIMEOUT;
+        let mut registry = self.registry.write().unwrap();
+        let registry = &mut *registry;
+        let before = registry.servers.len();
+        registry.servers.retain(|_, server| server.last_heartbeat.is_none_or(|heartbeat| heartbeat > cutoff));
+        let servers = &registry.servers;
+        registry.assignments.retain(|_, assignment| servers.contains_key(&assignment.server_id));
+        before - registry.servers.len()
+    }
+
+    /// Evicts stale servers forever, see [ServerRegistry::evict_stale].
+    pub async fn run_eviction(&self) {
+        let mut interval = tokio::time::interval(EVICTION_INTERVAL);
+        loop {
+            interval.tick().await;
+            let evicted = self.evict_stale();
+            if evicted > 0 {
+                info!("Evicted {} game servers that stopped sending heartbeats", evicted);
+            }
+        }
+    }
+
+    /// Ends the session the server is hosting and starts a new one with no players, so it can be
+    /// matched into again. Returns the server as it was before and as it is now, or [None] if it
+    /// isn't registered.
+    pub fn end_session(&self, server_id: &str) -> Option<(GameServer, GameServer)> {
+        let mut registry = self.registry.write().unwrap();
+        let server = registry.servers.get_mut(server_id)?;
+        let ended = server.clone();
+        server.session_id = Uuid::new_v4().to_stripped_string();
+        server.session_key = Uuid::new_v4().to_stripped_string();
+        server.players.clear();
+        server.reported_players = 0;
+        let started = server.clone();
+
+        for account_id in &ended.players {
+            registry.assignments.remove(account_id);
+        }
+        Some((ended, started))
+    }
+
+    pub fn servers(&self) -> Vec<GameServer> {
+        self.registry.read().unwrap().servers.values().cloned().collect()
+    }
+
+    pub fn session(&self, session_id: &str) -> Option<GameServer> {
+        self.registry.read().unwrap()
+            .servers
+            .values()
+            .find(|server| server.session_id == session_id)
+            .cloned()
+    }
+
+    pub fn assignment(&self, account_id: &str) -> Option<Assignment> {
+        self.registry.read().unwrap().assignments.get(account_id).cloned()
+    }
+
+    /// Reserves a slot for every account, returning the server along with the accounts that
+    /// didn't already have one there. If any of the accounts was already assigned, the rest join
+    /// them so parties stay together, as long as the server has room. Otherwise the server for the
+    /// playlist and region with the fewest open slots that still fits them all is picked, so
+    /// servers fill up one at a time. Returns [None] if there is no such server.
+    pub fn assign(&self, region: &str, playlist: &str, account_ids: &[String], build_unique_id: &str) -> Option<(GameServer, Vec<String>)> {
+        let mut registry = self.registry.write().unwrap();
+        let registry = &mut *registry;
+
+        let existing = account_ids.iter()
+            .find_map(|account_id| registry.assignments.get(account_id))
+            .map(|assignment| assignment.server_id.clone());
+        let existing = existing.filter(|server_id| registry.servers.get(server_id)
+            .is_some_and(|server| server.open_slots() as usize >= server.unreserved(account_ids)));
+        if existing.is_none() {
+            // Whoever was assigned can't be joined by the rest, so move everyone together.
+            Self::release_reservations(registry, account_ids);
+        }
+
+        let server = match existing {
+            Some(server_id) => registry.servers.get_mut(&server_id)?,
+            None => registry.servers.values_mut()
+                .filter(|server| server.serves(region, playlist) && server.open_slots() as usize >= server.unreserved(account_ids))
+                .min_by_key(|server| server.open_slots())?,
+        };
+
+        let mut reserved = vec![];
+        for account_id in account_ids {
+            if !server.players.contains(account_id) {
+                server.players.push(account_id.clone());
+                reserved.push(account_id.clone());
+            }
+        }
+        let server = server.clone();
+
+        for account_id in &reserved {
+            registry.assignments.insert(account_id.clone(), Assignment {
+                server_id: server.id.clone(),
+                build_unique_id: build_unique_id.to_string(),
+            });
+        }
+        Some((server, reserved))
+    }
+
+    /// Gives up the slots the accounts hold on the server, used when a client leaves the
+    /// matchmaker before it is told to join.
+    pub fn release(&self, server_id: &str, account_ids: &[String]) {
+        let mut registry = self.registry.write().unwrap();
+        let assigned = account_ids.iter()
+            .filter(|account_id| registry.assignments.get(*account_id).is_some_and(|assignment| assignment.server_id == server_id))
+            .cloned()
+            .collect::<Vec<_>>();
+        Self::release_reservations(&mut registry, &assigned);
+    }
+
+    fn release_reservations(registry: &mut Registry, account_ids: &[String]) {
+        for account_id in account_ids {
+            let Some(assignment) = registry.assignments.remove(account_id) else {
+                continue;
+            };
+            if let Some(server) = registry.servers.get_mut(&assignment.server_id) {
+                server.players.retain(|player| player != account_id);
+            }
+        }
+    }
+
+    /// Counts a ticket as waiting for a server until the returned guard is dropped.
+    pub fn enqueue(&self) -> QueueGuard<'_> {
+        self.queued.fetch_add(1, Ordering::Relaxed);
+        QueueGuard { registry: self }
+    }
+
+    pub fn queued(&self) -> u32 {
+        self.queued.load(Ordering::Relaxed)
+    }
+}
+
+pub struct QueueGuard<'a> {
+    registry: &'a ServerRegistry,
+}
+
+impl Drop for QueueGuard<'_> {
+    fn drop(&mut self) {
+        self.registry.queued.fetch_sub(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,79 @@
+use crate::athena::locker;
+use crate::epic::epic_error::{make_epic_err, EpicError};
+use crate::mcp::profile::{Profile, ProfileId};
+use crate::GlyphState;
+use axum::http::StatusCode;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+/// What should be sent back to the client once a command has run.
+pub enum CommandOutput {
+    /// Only the changes the command made to the profile.
+    Changes,
+    /// The whole profile, regardless of which revision the client has.
+    FullProfile,
+}
+
+fn operation_not_found(command: &str) -> Box<EpicError> {
+    Box::new(make_epic_err(
+        "errors.com.epicgames.fortnite.operation_not_found",
+        &format!("Operation {} not valid", command),
+        &[command.to_string()],
+        16035,
+        StatusCode::NOT_FOUND,
+    ))
+}
+
+fn invalid_command(command: &str, profile_id: ProfileId) -> Box<EpicError> {
+    Box::new(make_epic_err(
+        "errors.com.epicgames.modules.profiles.invalid_command",
+        &format!("{} is not valid on {} profiles", command, profile_id),
+        &[command.to_string(), profile_id.to_string()],
+        12801,
+        StatusCode::BAD_REQUEST,
+    ))
+}
+
+/// Deserializes the body of a command into the supplied type.
+pub fn parse_body<T: DeserializeOwned>(body: &Value) -> Result<T, Box<EpicError>> {
+    serde_json::from_value(body.clone()).map_err(|e| {
+        Box::new(make_epic_err(
+            "errors.com.epicgames.validation.validation_failed",
+            &format!("Validation Failed. {}", e),
+            &[],
+            1040,
+            StatusCode::BAD_REQUEST,
+        ))
+    })
+}
+
+/// Fails with an error if the command is run against any profile other than the supplied one.
+fn require_profile(command: &str, profile: &Profile, profile_id: ProfileId) -> Result<(), Box<EpicError>> {
+    if profile.profile_id == profile_id {
+        Ok(())
+    } else {
+        Err(invalid_command(command, profile.profile_id))
+    }
+}
+
+/// Runs the named MCP command against the supplied profile. Changes are recorded on the profile
+/// and are committed and saved by the caller.
+pub async fn run_command(
+    state: &GlyphState,
+    command: &str,
+    profile: &mut Profile,
+    body: &Value,
+) -> Result<CommandOutput, Box<EpicError>> {
+    match command {
+        "QueryProfile" | "ClientQuestLogin" => Ok(CommandOutput::FullProfile),
+        "EquipBattleRoyaleCustomization" => {
+            require_profile(command, profile, ProfileId::Athena)?;
+            locker::equip_battle_royale_customization(state, profile, body)
+        }
+        "SetCosmeticLockerSlot" => {
+            require_profile(command, profile, ProfileId::Athena)?;
+            locker::set_cosmetic_locker_slot(state, profile, body)
+        }
+        _ => Err(operation_not_found(command)),
+    }
+}
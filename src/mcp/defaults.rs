@@ -0,0 +1,117 @@
+use crate::athena::items::{ItemManager, ItemType};
+use crate::athena::locker::{slot_size, LOCKER_ITEM_ID, LOCKER_SLOTS, LOCKER_TEMPLATE_ID};
+use crate::error;
+use crate::error::Error;
+use crate::mcp::profile::{get_profile, insert_profile, Profile, ProfileId, ProfileItem};
+use crate::mongo::{is_duplicate_key, GlyphMongo};
+use serde_json::{json, Map, Value};
+use uuid::Uuid;
+
+fn object(value: Value) -> Map<String, Value> {
+    match value {
+        Value::Object(map) => map,
+        _ => Map::new(),
+    }
+}
+
+/// Builds the profile a player starts with the first time it is queried.
+pub fn default_profile(account_id: &Uuid, profile_id: ProfileId, item_manager: &ItemManager) -> Profile {
+    let mut profile = Profile::new(account_id, profile_id);
+    match profile_id {
+        ProfileId::Athena => default_athena(&mut profile, item_manager),
+        ProfileId::CommonCore => default_common_core(&mut profile, item_manager),
+        ProfileId::CommonPublic => {}
+    }
+    profile
+}
+
+/// Returns the stored profile, creating the default one if the account doesn't have it yet.
+pub async fn get_or_create_profile(
+    mongo: &GlyphMongo,
+    account_id: &Uuid,
+    profile_id: ProfileId,
+    item_manager: &ItemManager,
+) -> error::Result<Profile> {
+    if let Some(profile) = get_profile(mongo, account_id, profile_id).await? {
+        return Ok(profile);
+    }
+
+    let profile = default_profile(account_id, profile_id, item_manager);
+    match insert_profile(mongo, &profile).await {
+        Ok(()) => Ok(profile),
+        // Another request created the profile between the lookup and the insert, so use theirs.
+        Err(Error::MongoError(e)) if is_duplicate_key(&e) => {
+            get_profile(mongo, account_id, profile_id).await?.ok_or(Error::MongoError(e))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Grants every locker item in the catalogue and leaves every slot empty so the client falls
+/// back to the default loadout.
+fn default_athena(profile: &mut Profile, item_manager: &ItemManager) {
+    for template_id in item_manager.locker_items() {
+        let attributes = object(json!({
+            "max_level_bonus": 0,
+            "level": 1,
+            "item_seen": true,
+            "xp": 0,
+            "variants": [],
+            "favorite": false,
+        }));
+        profile.items.insert(template_id.clone(), ProfileItem::new(template_id, attributes));
+    }
+
+    profile.stats.attributes = object(json!({
+        "level": 1,
+        "xp": 0,
+        "book_level": 1,
+        "book_xp": 0,
+        "book_purchased": false,
+        "lifetime_wins": 0,
+        "favorite_character": "",
+        "favorite_backpack": "",
+        "favorite_pickaxe": "",
+        "favorite_glider": "",
+        "favorite_skydivecontrail": "",
+        "favorite_dance": ["", "", "", "", "", ""],
+        "favorite_itemwraps": ["", "", "", "", "", "", ""],
+        "favorite_musicpack": "",
+        "favorite_loadingscreen": "",
+        "banner_icon": "",
+        "banner_color": "",
+        "loadouts": [LOCKER_ITEM_ID],
+        "active_loadout_index": 0,
+    }));
+
+    let slots = LOCKER_SLOTS.iter()
+        .map(|slot| (format!("{:?}", slot), json!({ "items": vec![""; slot_size(slot)] })))
+        .collect::<Map<String, Value>>();
+    let locker = ProfileItem::new(LOCKER_TEMPLATE_ID, object(json!({
+        "locker_slots_data": { "slots": slots },
+        "use_count": 0,
+        "banner_icon_template": "",
+        "banner_color_template": "",
+        "locker_name": "",
+        "item_seen": false,
+        "favorite": false,
+    })));
+    profile.items.insert(LOCKER_ITEM_ID.to_string(), locker);
+}
+
+fn default_common_core(profile: &mut Profile, item_manager: &ItemManager) {
+    for template_id in item_manager.items_of(&ItemType::MiscItem) {
+        let mut item = ProfileItem::new(template_id, object(json!({ "platform": "EpicPC" })));
+        item.quantity = 0;
+        profile.items.insert(template_id.clone(), item);
+    }
+
+    profile.stats.attributes = object(json!({
+        "mtx_purchase_history": {},
+        "current_mtx_platform": "EpicPC",
+        "mtx_affiliate": "",
+        "allowed_to_receive_gifts": true,
+        "allowed_to_send_gifts": true,
+        "gift_history": {},
+    }));
+}
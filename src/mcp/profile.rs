@@ -0,0 +1,222 @@
+use crate::error;
+use crate::mongo::{GlyphMongo, ATHENA_COLL, COMMON_CORE_COLL, COMMON_PUB_COLL, PROFILE_DB};
+use crate::serializers;
+use crate::util::UuidString;
+use bson::doc;
+use chrono::{DateTime, Utc};
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::str::FromStr;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ProfileId {
+    #[serde(rename = "athena")]
+    Athena,
+    #[serde(rename = "common_core")]
+    CommonCore,
+    #[serde(rename = "common_public")]
+    CommonPublic,
+}
+
+impl ProfileId {
+    /// The collection in [PROFILE_DB] that profiles of this type are stored in.
+    pub fn collection(&self) -> &'static str {
+        match self {
+            ProfileId::Athena => ATHENA_COLL,
+            ProfileId::CommonCore => COMMON_CORE_COLL,
+            ProfileId::CommonPublic => COMMON_PUB_COLL,
+        }
+    }
+}
+
+impl Display for ProfileId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let str = match self {
+            ProfileId::Athena => "athena",
+            ProfileId::CommonCore => "common_core",
+            ProfileId::CommonPublic => "common_public",
+        };
+        write!(f, "{}", str)
+    }
+}
+
+impl FromStr for ProfileId {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<ProfileId, Self::Err> {
+        match input {
+            "athena" => Ok(ProfileId::Athena),
+            "common_core" => Ok(ProfileId::CommonCore),
+            "common_public" => Ok(ProfileId::CommonPublic),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ProfileItem {
+    #[serde(rename = "templateId")]
+    pub(crate) template_id: String,
+    pub(crate) attributes: Map<String, Value>,
+    pub(crate) quantity: i64,
+}
+
+impl ProfileItem {
+    pub fn new(template_id: &str, attributes: Map<String, Value>) -> Self {
+        Self {
+            template_id: template_id.to_string(),
+            attributes,
+            quantity: 1,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct ProfileStats {
+    pub(crate) attributes: Map<String, Value>,
+}
+
+/// A single entry of the `profileChanges` array sent back to the client after a command.
+#[derive(Serialize, Clone)]
+#[serde(tag = "changeType", rename_all = "camelCase")]
+pub enum ProfileChange {
+    FullProfileUpdate {
+        profile: Profile,
+    },
+    StatModified {
+        name: String,
+        value: Value,
+    },
+    ItemAttrChanged {
+        #[serde(rename = "itemId")]
+        item_id: String,
+        #[serde(rename = "attributeName")]
+        attribute_name: String,
+        #[serde(rename = "attributeValue")]
+        attribute_value: Value,
+    },
+}
+
+/// An MCP profile, stored in the same shape the client expects inside a `fullProfileUpdate`.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Profile {
+    #[serde(rename = "_id")]
+    pub(crate) id: String,
+    #[serde(serialize_with = "serializers::serialize_datetime", deserialize_with = "serializers::deserialize_datetime")]
+    pub(crate) created: DateTime<Utc>,
+    #[serde(serialize_with = "serializers::serialize_datetime", deserialize_with = "serializers::deserialize_datetime")]
+    pub(crate) updated: DateTime<Utc>,
+    pub(crate) rvn: i64,
+    #[serde(rename = "wipeNumber")]
+    pub(crate) wipe_number: i64,
+    #[serde(rename = "accountId")]
+    pub(crate) account_id: String,
+    #[serde(rename = "profileId")]
+    pub(crate) profile_id: ProfileId,
+    pub(crate) version: String,
+    pub(crate) items: HashMap<String, ProfileItem>,
+    pub(crate) stats: ProfileStats,
+    #[serde(rename = "commandRevision")]
+    pub(crate) command_revision: i64,
+    /// Changes made since the profile was loaded, see [Profile::commit].
+    #[serde(skip)]
+    changes: Vec<ProfileChange>,
+}
+
+impl Profile {
+    /// Creates an empty profile for the supplied account. Nothing is stored until
+    /// [insert_profile] is called.
+    pub fn new(account_id: &Uuid, profile_id: ProfileId) -> Self {
+        let now = Utc::now();
+        let account_id = account_id.to_stripped_string();
+        Self {
+            id: account_id.clone(),
+            created: now,
+            updated: now,
+            rvn: 1,
+            wipe_number: 1,
+            account_id,
+            profile_id,
+            version: "glyph".to_string(),
+            items: HashMap::new(),
+            stats: ProfileStats::default(),
+            command_revision: 0,
+            changes: vec![],
+        }
+    }
+
+    pub fn stat(&self, name: &str) -> Option<&Value> {
+        self.stats.attributes.get(name)
+    }
+
+    pub fn set_stat(&mut self, name: &str, value: Value) {
+        self.stats.attributes.insert(name.to_string(), value.clone());
+        self.changes.push(ProfileChange::StatModified {
+            name: name.to_string(),
+            value,
+        });
+    }
+
+    /// Returns false if no item with the supplied ID exists.
+    pub fn set_item_attribute(&mut self, item_id: &str, name: &str, value: Value) -> bool {
+        let Some(item) = self.items.get_mut(item_id) else {
+            return false;
+        };
+        item.attributes.insert(name.to_string(), value.clone());
+        self.changes.push(ProfileChange::ItemAttrChanged {
+            item_id: item_id.to_string(),
+            attribute_name: name.to_string(),
+            attribute_value: value,
+        });
+        true
+    }
+
+    /// Bumps the revision and command revision if anything changed since the profile was loaded
+    /// or last committed, then drains and returns the pending changes.
+    pub fn commit(&mut self) -> Vec<ProfileChange> {
+        if !self.changes.is_empty() {
+            self.rvn += 1;
+            self.command_revision += 1;
+            self.updated = Utc::now();
+        }
+        std::mem::take(&mut self.changes)
+    }
+
+    /// Returns a `fullProfileUpdate` change containing the current state of the profile.
+    pub fn full_update(&self) -> ProfileChange {
+        ProfileChange::FullProfileUpdate {
+            profile: self.clone(),
+        }
+    }
+}
+
+/// How many times a change to a profile is attempted before giving up, see [save_profile].
+pub const SAVE_ATTEMPTS: u32 = 3;
+
+async fn profile_collection(mongo: &GlyphMongo, profile_id: ProfileId) -> Collection<Profile> {
+    mongo.collection::<Profile>(PROFILE_DB, profile_id.collection()).await
+}
+
+pub async fn get_profile(mongo: &GlyphMongo, account_id: &Uuid, profile_id: ProfileId) -> error::Result<Option<Profile>> {
+    let filter = doc! { "_id": account_id.to_stripped_string() };
+    let found = profile_collection(mongo, profile_id).await.find_one(filter).await?;
+    Ok(found)
+}
+
+pub async fn insert_profile(mongo: &GlyphMongo, profile: &Profile) -> error::Result<()> {
+    profile_collection(mongo, profile.profile_id).await.insert_one(profile).await?;
+    Ok(())
+}
+
+/// Replaces the stored copy of the supplied profile, as long as it is still at `base_revision`,
+/// the revision it was loaded at. Returns false if another request saved it first, in which case
+/// the caller should load it again and redo its changes, up to [SAVE_ATTEMPTS] times.
+pub async fn save_profile(mongo: &GlyphMongo, profile: &Profile, base_revision: i64) -> error::Result<bool> {
+    let filter = doc! { "_id": &profile.id, "rvn": base_revision };
+    let result = profile_collection(mongo, profile.profile_id).await.replace_one(filter, profile).await?;
+    Ok(result.matched_count > 0)
+}
@@ -1,4 +1,5 @@
 use crate::error;
+use mongodb::error::{ErrorKind, WriteFailure};
 use mongodb::options::ClientOptions;
 use mongodb::{Client, Collection};
 
@@ -24,12 +25,38 @@ impl GlyphMongo {
     }
 }
 
+/// Returns true if the error was caused by a write that would break a unique index.
+pub fn is_duplicate_key(error: &mongodb::error::Error) -> bool {
+    const DUPLICATE_KEY: i32 = 11000;
+    match error.kind.as_ref() {
+        ErrorKind::Command(e) => e.code == DUPLICATE_KEY,
+        ErrorKind::Write(WriteFailure::WriteError(e)) => e.code == DUPLICATE_KEY,
+        _ => false,
+    }
+}
+
+/// Returns the name of the unique index a write would have broken, like `display_name_1`, or
+/// [None] if the error wasn't caused by a duplicate key.
+pub fn duplicate_key_index(error: &mongodb::error::Error) -> Option<&str> {
+    if !is_duplicate_key(error) {
+        return None;
+    }
+    let message = match error.kind.as_ref() {
+        ErrorKind::Command(e) => &e.message,
+        ErrorKind::Write(WriteFailure::WriteError(e)) => &e.message,
+        _ => return None,
+    };
+    // The server reports the index as `... index: <name> dup key: { ... }`.
+    message.split_once("index: ")?.1.split_whitespace().next()
+}
+
 // Database and collection names
 
 pub const AUTH_DB: &str = "auth";
 pub const ACCESS_TKN_COLL: &str = "access";
 pub const EXCHANGE_CODE_COLL: &str = "exchange";
 pub const REFRESH_TKN_COLL: &str = "refresh";
+pub const SIGNING_KEY_COLL: &str = "signing_key";
 
 pub const PROFILE_DB: &str = "profile";
 pub const ATHENA_COLL: &str = "athena";
@@ -39,3 +66,11 @@ pub const COMMON_PUB_COLL: &str = "common_public";
 pub const USER_DB: &str = "user";
 pub const USERS_COLL: &str = "user";
 pub const FRIENDS_COLL: &str = "friend";
+pub const ROLES_COLL: &str = "role";
+
+pub const PARTY_DB: &str = "party";
+pub const PARTY_COLL: &str = "party";
+
+pub const STATS_DB: &str = "stats";
+pub const MATCHES_COLL: &str = "match";
+pub const STATS_COLL: &str = "stat";
@@ -0,0 +1,714 @@
+use crate::error;
+use crate::error::Error;
+use crate::mongo::{GlyphMongo, PARTY_COLL, PARTY_DB};
+use crate::serializers;
+use crate::util::UuidString;
+use crate::xmpp::hub::XmppHub;
+use bson::doc;
+use chrono::{DateTime, SecondsFormat, TimeDelta, Utc};
+use log::info;
+use mongodb::options::ReplaceOptions;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use uuid::Uuid;
+
+/// The namespace every party notification is sent in.
+pub const PARTY_NS: &str = "Fortnite";
+const NOTIFICATION_PREFIX: &str = "com.epicgames.social.party.notification.v0";
+const DEFAULT_MAX_SIZE: u64 = 16;
+/// How long invites and pings last, in seconds.
+const INVITE_TTL: i64 = 14400;
+/// The member meta key the client stores its display name in.
+const DISPLAY_NAME_META: &str = "urn:epic:member:dn_s";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum PartyRole {
+    Captain,
+    Member,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PartyConnection {
+    /// The XMPP JID of the client.
+    id: String,
+    #[serde(serialize_with = "serializers::serialize_datetime", deserialize_with = "serializers::deserialize_datetime")]
+    connected_at: DateTime<Utc>,
+    #[serde(serialize_with = "serializers::serialize_datetime", deserialize_with = "serializers::deserialize_datetime")]
+    updated_at: DateTime<Utc>,
+    yield_leadership: bool,
+    meta: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PartyMember {
+    pub(crate) account_id: String,
+    meta: Map<String, Value>,
+    connections: Vec<PartyConnection>,
+    revision: i64,
+    #[serde(serialize_with = "serializers::serialize_datetime", deserialize_with = "serializers::deserialize_datetime")]
+    updated_at: DateTime<Utc>,
+    #[serde(serialize_with = "serializers::serialize_datetime", deserialize_with = "serializers::deserialize_datetime")]
+    joined_at: DateTime<Utc>,
+    pub(crate) role: PartyRole,
+}
+
+impl PartyMember {
+    fn display_name(&self) -> &str {
+        self.meta.get(DISPLAY_NAME_META).and_then(Value::as_str).unwrap_or(&self.account_id)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PartyInvite {
+    party_id: String,
+    sent_by: String,
+    meta: Map<String, Value>,
+    sent_to: String,
+    #[serde(serialize_with = "serializers::serialize_datetime", deserialize_with = "serializers::deserialize_datetime")]
+    sent_at: DateTime<Utc>,
+    #[serde(serialize_with = "serializers::serialize_datetime", deserialize_with = "serializers::deserialize_datetime")]
+    updated_at: DateTime<Utc>,
+    #[serde(serialize_with = "serializers::serialize_datetime", deserialize_with = "serializers::deserialize_datetime")]
+    expires_at: DateTime<Utc>,
+    status: String,
+}
+
+/// A request from one player for another to join their party. Pings only live in memory.
+#[derive(Serialize, Clone)]
+pub struct PartyPing {
+    sent_by: String,
+    sent_to: String,
+    #[serde(serialize_with = "serializers::serialize_datetime")]
+    sent_at: DateTime<Utc>,
+    #[serde(serialize_with = "serializers::serialize_datetime")]
+    expires_at: DateTime<Utc>,
+    meta: Map<String, Value>,
+}
+
+/// A party in the shape the client expects from the party service.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Party {
+    pub(crate) id: String,
+    #[serde(serialize_with = "serializers::serialize_datetime", deserialize_with = "serializers::deserialize_datetime")]
+    created_at: DateTime<Utc>,
+    #[serde(serialize_with = "serializers::serialize_datetime", deserialize_with = "serializers::deserialize_datetime")]
+    updated_at: DateTime<Utc>,
+    config: Map<String, Value>,
+    pub(crate) members: Vec<PartyMember>,
+    applicants: Vec<Value>,
+    meta: Map<String, Value>,
+    invites: Vec<PartyInvite>,
+    revision: i64,
+    intentions: Vec<Value>,
+}
+
+impl Party {
+    pub fn member(&self, account_id: &str) -> Option<&PartyMember> {
+        self.members.iter().find(|member| member.account_id == account_id)
+    }
+
+    pub fn captain(&self) -> Option<&PartyMember> {
+        self.members.iter().find(|member| member.role == PartyRole::Captain)
+    }
+
+    pub fn max_size(&self) -> usize {
+        self.config.get("max_size").and_then(Value::as_u64).unwrap_or(DEFAULT_MAX_SIZE) as usize
+    }
+
+    fn require_captain(&self, account_id: &str) -> error::Result<()> {
+        match self.member(account_id) {
+            Some(member) if member.role == PartyRole::Captain => Ok(()),
+            Some(_) => Err(Error::NotPartyCaptain),
+            None => Err(Error::PartyMemberNotFound(account_id.to_string())),
+        }
+    }
+
+    fn touch(&mut self) {
+        self.revision += 1;
+        self.updated_at = Utc::now();
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ConnectionInfo {
+    id: String,
+    #[serde(default)]
+    meta: Map<String, Value>,
+    #[serde(default)]
+    yield_leadership: bool,
+}
+
+#[derive(Deserialize)]
+pub struct JoinInfo {
+    connection: ConnectionInfo,
+    #[serde(default)]
+    meta: Map<String, Value>,
+}
+
+#[derive(Deserialize)]
+pub struct CreateParty {
+    #[serde(default)]
+    config: Map<String, Value>,
+    join_info: JoinInfo,
+    #[serde(default)]
+    meta: Map<String, Value>,
+}
+
+/// A change to party or member meta. `revision` is the revision the client last saw, if it
+/// doesn't match the change is rejected so the client can catch up first.
+#[derive(Deserialize, Default)]
+pub struct MetaPatch {
+    #[serde(default)]
+    pub(crate) delete: Vec<String>,
+    #[serde(default)]
+    pub(crate) update: Map<String, Value>,
+    revision: Option<i64>,
+}
+
+impl MetaPatch {
+    fn apply(&self, meta: &mut Map<String, Value>) {
+        for key in &self.delete {
+            meta.remove(key);
+        }
+        for (key, value) in &self.update {
+            meta.insert(key.clone(), value.clone());
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct PartyPatch {
+    #[serde(default)]
+    config: Map<String, Value>,
+    #[serde(default)]
+    pub(crate) meta: MetaPatch,
+    revision: Option<i64>,
+}
+
+fn default_config() -> Map<String, Value> {
+    let config = json!({
+        "type": "DEFAULT",
+        "joinability": "OPEN",
+        "discoverability": "ALL",
+        "sub_type": "default",
+        "max_size": DEFAULT_MAX_SIZE,
+        "invite_ttl": INVITE_TTL,
+        "join_confirmation": false,
+        "intention_ttl": 60,
+    });
+    match config {
+        Value::Object(map) => map,
+        _ => unreachable!("the default config is an object"),
+    }
+}
+
+fn check_revision(current: i64, supplied: Option<i64>) -> error::Result<()> {
+    match supplied {
+        Some(supplied) if supplied != current => Err(Error::StalePartyRevision(current, supplied)),
+        _ => Ok(()),
+    }
+}
+
+fn new_member(account_id: &str, join_info: &JoinInfo, role: PartyRole) -> PartyMember {
+    let now = Utc::now();
+    PartyMember {
+        account_id: account_id.to_string(),
+        meta: join_info.meta.clone(),
+        connections: vec![PartyConnection {
+            id: join_info.connection.id.clone(),
+            connected_at: now,
+            updated_at: now,
+            yield_leadership: join_info.connection.yield_leadership,
+            meta: join_info.connection.meta.clone(),
+        }],
+        revision: 0,
+        updated_at: now,
+        joined_at: now,
+        role,
+    }
+}
+
+/// What happened when a member left or was kicked.
+pub struct PartyDeparture {
+    pub(crate) member: PartyMember,
+    /// The party after the member left, or [None] if it was the last one and the party is gone.
+    pub(crate) party: Option<Party>,
+    /// Whether the member that left was the captain, so someone else was promoted.
+    pub(crate) new_captain: bool,
+}
+
+/// Every active party and ping. Parties are also written to Mongo when `PERSIST_PARTIES` is set
+/// so they survive restarts.
+pub struct PartyManager {
+    parties: RwLock<HashMap<String, Party>>,
+    pings: RwLock<Vec<PartyPing>>,
+    /// Held from changing a party until it is saved, so saves of the same party reach Mongo in
+    /// the order the changes were made. Keyed by party ID.
+    save_locks: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+    persist: bool,
+}
+
+impl PartyManager {
+    pub async fn new(mongo: &GlyphMongo) -> error::Result<Self> {
+        let persist = std::env::var("PERSIST_PARTIES").is_ok_and(|value| value == "true");
+        let mut parties = HashMap::new();
+        if persist {
+            let collection = mongo.collection::<Party>(PARTY_DB, PARTY_COLL).await;
+            let mut cursor = collection.find(doc! {}).await?;
+            while cursor.advance().await? {
+                let party = cursor.deserialize_current()?;
+                parties.insert(party.id.clone(), party);
+            }
+            info!("Loaded {} persisted parties", parties.len());
+        }
+
+        Ok(Self {
+            parties: RwLock::new(parties),
+            pings: RwLock::new(vec![]),
+            save_locks: Mutex::new(HashMap::new()),
+            persist,
+        })
+    }
+
+    async fn save(&self, mongo: &GlyphMongo, party: &Party) -> error::Result<()> {
+        if self.persist {
+            mongo.collection::<Party>(PARTY_DB, PARTY_COLL).await
+                .replace_one(doc! { "id": &party.id }, party)
+                .with_options(ReplaceOptions::builder().upsert(true).build())
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn delete(&self, mongo: &GlyphMongo, party_id: &str) -> error::Result<()> {
+        if self.persist {
+            mongo.collection::<Party>(PARTY_DB, PARTY_COLL).await
+                .delete_one(doc! { "id": party_id })
+                .await?;
+        }
+        Ok(())
+    }
+
+    fn save_lock(&self, party_id: &str) -> Arc<tokio::sync::Mutex<()>> {
+        self.save_locks.lock().unwrap().entry(party_id.to_string()).or_default().clone()
+    }
+
+    /// Applies a change to a party under the lock, returns a copy of the result and persists it.
+    async fn modify<T>(
+        &self,
+        mongo: &GlyphMongo,
+        party_id: &str,
+        change: impl FnOnce(&mut Party) -> error::Result<T>,
+    ) -> error::Result<(Party, T)> {
+        let save_lock = self.save_lock(party_id);
+        let _saving = save_lock.lock().await;
+        let (party, output) = {
+            let mut parties = self.parties.write().unwrap();
+            let party = parties.get_mut(party_id).ok_or_else(|| Error::PartyNotFound(party_id.to_string()))?;
+            let output = change(party)?;
+            (party.clone(), output)
+        };
+        self.save(mongo, &party).await?;
+        Ok((party, output))
+    }
+
+    pub fn get(&self, party_id: &str) -> error::Result<Party> {
+        self.parties.read().unwrap()
+            .get(party_id)
+            .cloned()
+            .ok_or_else(|| Error::PartyNotFound(party_id.to_string()))
+    }
+
+    /// Returns the party the account is a member of, if any.
+    pub fn party_of(&self, account_id: &str) -> Option<Party> {
+        self.parties.read().unwrap()
+            .values()
+            .find(|party| party.member(account_id).is_some())
+            .cloned()
+    }
+
+    pub fn invites_for(&self, account_id: &str) -> Vec<PartyInvite> {
+        let now = Utc::now();
+        self.parties.read().unwrap()
+            .values()
+            .flat_map(|party| party.invites.iter())
+            .filter(|invite| invite.sent_to == account_id && invite.expires_at > now)
+            .cloned()
+            .collect()
+    }
+
+    pub fn pings_for(&self, account_id: &str) -> Vec<PartyPing> {
+        let now = Utc::now();
+        self.pings.read().unwrap()
+            .iter()
+            .filter(|ping| ping.sent_to == account_id && ping.expires_at > now)
+            .cloned()
+            .collect()
+    }
+
+    /// Creates a party with the account as its captain.
+    pub async fn create(&self, mongo: &GlyphMongo, account_id: &str, body: CreateParty) -> error::Result<Party> {
+        let mut config = default_config();
+        config.extend(body.config);
+        let now = Utc::now();
+        let party = Party {
+            id: Uuid::new_v4().to_stripped_string(),
+            created_at: now,
+            updated_at: now,
+            config,
+            members: vec![new_member(account_id, &body.join_info, PartyRole::Captain)],
+            applicants: vec![],
+            meta: body.meta,
+            invites: vec![],
+            revision: 0,
+            intentions: vec![],
+        };
+
+        self.parties.write().unwrap().insert(party.id.clone(), party.clone());
+        self.save(mongo, &party).await?;
+        Ok(party)
+    }
+
+    /// Adds the account to the party if it is open or the account was invited or pinged by a
+    /// member. Joining a party the account is already in just replaces its connection.
+    pub async fn join(&self, mongo: &GlyphMongo, party_id: &str, account_id: &str, join_info: JoinInfo) -> error::Result<Party> {
+        let pinged = self.pings.read().unwrap()
+            .iter()
+            .filter(|ping| ping.sent_to == account_id && ping.expires_at > Utc::now())
+            .map(|ping| ping.sent_by.clone())
+            .collect::<Vec<_>>();
+
+        let (party, ()) = self.modify(mongo, party_id, |party| {
+            if let Some(member) = party.members.iter_mut().find(|member| member.account_id == account_id) {
+                member.connections = new_member(account_id, &join_info, member.role).connections;
+                return Ok(());
+            }
+
+            let open = party.config.get("joinability").and_then(Value::as_str) == Some("OPEN");
+            let invited = party.invites.iter().any(|invite| invite.sent_to == account_id && invite.expires_at > Utc::now());
+            let pinged = party.members.iter().any(|member| pinged.contains(&member.account_id));
+            if !open && !invited && !pinged {
+                return Err(Error::PartyJoinForbidden);
+            }
+            if party.members.len() >= party.max_size() {
+                return Err(Error::PartyFull);
+            }
+
+            party.invites.retain(|invite| invite.sent_to != account_id);
+            party.members.push(new_member(account_id, &join_info, PartyRole::Member));
+            party.touch();
+            Ok(())
+        }).await?;
+
+        let members = party.members.iter().map(|member| member.account_id.as_str()).collect::<Vec<_>>();
+        self.pings.write().unwrap()
+            .retain(|ping| !(ping.sent_to == account_id && members.contains(&ping.sent_by.as_str())));
+        Ok(party)
+    }
+
+    /// Removes a member from the party, promoting the longest standing member if they were the
+    /// captain and deleting the party if they were the last one.
+    pub async fn remove_member(&self, mongo: &GlyphMongo, party_id: &str, account_id: &str) -> error::Result<PartyDeparture> {
+        self.remove(mongo, party_id, account_id, None).await
+    }
+
+    pub async fn kick(&self, mongo: &GlyphMongo, party_id: &str, captain_id: &str, account_id: &str) -> error::Result<PartyDeparture> {
+        self.remove(mongo, party_id, account_id, Some(captain_id)).await
+    }
+
+    /// See [PartyManager::remove_member]. If `captain_id` is set, the member is only removed if
+    /// that account is the captain when the party is locked.
+    async fn remove(&self, mongo: &GlyphMongo, party_id: &str, account_id: &str, captain_id: Option<&str>) -> error::Result<PartyDeparture> {
+        let save_lock = self.save_lock(party_id);
+        let _saving = save_lock.lock().await;
+        let departure = {
+            let mut parties = self.parties.write().unwrap();
+            let party = parties.get_mut(party_id).ok_or_else(|| Error::PartyNotFound(party_id.to_string()))?;
+            if let Some(captain_id) = captain_id {
+                party.require_captain(captain_id)?;
+            }
+            let index = party.members.iter()
+                .position(|member| member.account_id == account_id)
+                .ok_or_else(|| Error::PartyMemberNotFound(account_id.to_string()))?;
+            let member = party.members.remove(index);
+
+            if party.members.is_empty() {
+                parties.remove(party_id);
+                PartyDeparture { member, party: None, new_captain: false }
+            } else {
+                let new_captain = member.role == PartyRole::Captain;
+                if new_captain {
+                    let next = party.members.iter_mut()
+                        .min_by_key(|member| member.joined_at)
+                        .expect("the party isn't empty");
+                    next.role = PartyRole::Captain;
+                }
+                party.touch();
+                PartyDeparture { member, party: Some(party.clone()), new_captain }
+            }
+        };
+
+        match &departure.party {
+            Some(party) => self.save(mongo, party).await?,
+            None => {
+                self.delete(mongo, party_id).await?;
+                self.save_locks.lock().unwrap().remove(party_id);
+            }
+        }
+        Ok(departure)
+    }
+
+    pub async fn promote(&self, mongo: &GlyphMongo, party_id: &str, captain_id: &str, account_id: &str) -> error::Result<Party> {
+        let (party, ()) = self.modify(mongo, party_id, |party| {
+            party.require_captain(captain_id)?;
+            if party.member(account_id).is_none() {
+                return Err(Error::PartyMemberNotFound(account_id.to_string()));
+            }
+            for member in party.members.iter_mut() {
+                member.role = if member.account_id == account_id { PartyRole::Captain } else { PartyRole::Member };
+            }
+            party.touch();
+            Ok(())
+        }).await?;
+        Ok(party)
+    }
+
+    pub async fn update_party(&self, mongo: &GlyphMongo, party_id: &str, account_id: &str, patch: &PartyPatch) -> error::Result<Party> {
+        let (party, ()) = self.modify(mongo, party_id, |party| {
+            party.require_captain(account_id)?;
+            check_revision(party.revision, patch.revision.or(patch.meta.revision))?;
+            party.config.extend(patch.config.clone());
+            patch.meta.apply(&mut party.meta);
+            party.touch();
+            Ok(())
+        }).await?;
+        Ok(party)
+    }
+
+    pub async fn update_member_meta(&self, mongo: &GlyphMongo, party_id: &str, account_id: &str, patch: &MetaPatch) -> error::Result<Party> {
+        let (party, ()) = self.modify(mongo, party_id, |party| {
+            let member = party.members.iter_mut()
+                .find(|member| member.account_id == account_id)
+                .ok_or_else(|| Error::PartyMemberNotFound(account_id.to_string()))?;
+            check_revision(member.revision, patch.revision)?;
+            patch.apply(&mut member.meta);
+            member.revision += 1;
+            member.updated_at = Utc::now();
+            party.updated_at = Utc::now();
+            Ok(())
+        }).await?;
+        Ok(party)
+    }
+
+    /// Invites an account to the party, replacing any earlier invite it had to the same party.
+    pub async fn invite(
+        &self,
+        mongo: &GlyphMongo,
+        party_id: &str,
+        inviter_id: &str,
+        invitee_id: &str,
+        meta: Map<String, Value>,
+    ) -> error::Result<(Party, PartyInvite)> {
+        self.modify(mongo, party_id, |party| {
+            if party.member(inviter_id).is_none() {
+                return Err(Error::PartyMemberNotFound(inviter_id.to_string()));
+            }
+            let now = Utc::now();
+            let invite = PartyInvite {
+                party_id: party.id.clone(),
+                sent_by: inviter_id.to_string(),
+                meta,
+                sent_to: invitee_id.to_string(),
+                sent_at: now,
+                updated_at: now,
+                expires_at: now + TimeDelta::seconds(INVITE_TTL),
+                status: "SENT".to_string(),
+            };
+            party.invites.retain(|existing| existing.sent_to != invitee_id);
+            party.invites.push(invite.clone());
+            Ok(invite)
+        }).await
+    }
+
+    pub async fn decline_invite(&self, mongo: &GlyphMongo, party_id: &str, invitee_id: &str) -> error::Result<(Party, PartyInvite)> {
+        self.modify(mongo, party_id, |party| {
+            let index = party.invites.iter()
+                .position(|invite| invite.sent_to == invitee_id)
+                .ok_or(Error::PartyInviteNotFound)?;
+            Ok(party.invites.remove(index))
+        }).await
+    }
+
+    /// Pings an account, replacing any ping the pinger already sent it.
+    pub fn ping(&self, pinger_id: &str, account_id: &str, meta: Map<String, Value>) -> PartyPing {
+        let now = Utc::now();
+        let ping = PartyPing {
+            sent_by: pinger_id.to_string(),
+            sent_to: account_id.to_string(),
+            sent_at: now,
+            expires_at: now + TimeDelta::seconds(INVITE_TTL),
+            meta,
+        };
+        let mut pings = self.pings.write().unwrap();
+        pings.retain(|existing| existing.expires_at > now && !(existing.sent_by == pinger_id && existing.sent_to == account_id));
+        pings.push(ping.clone());
+        ping
+    }
+
+    /// Returns false if there was no ping to delete.
+    pub fn delete_ping(&self, pinger_id: &str, account_id: &str) -> bool {
+        let mut pings = self.pings.write().unwrap();
+        let before = pings.len();
+        pings.retain(|ping| !(ping.sent_by == pinger_id && ping.sent_to == account_id));
+        pings.len() != before
+    }
+
+    /// Returns the party of a player that pinged the account.
+    pub fn pinger_parties(&self, account_id: &str, pinger_id: &str) -> error::Result<Vec<Party>> {
+        if !self.pings_for(account_id).iter().any(|ping| ping.sent_by == pinger_id) {
+            return Err(Error::PartyPingNotFound);
+        }
+        Ok(self.party_of(pinger_id).into_iter().collect())
+    }
+}
+
+fn date(date: DateTime<Utc>) -> String {
+    date.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// Starts a party notification with the fields every one of them has.
+fn notification(kind: &str, party: &Party) -> Map<String, Value> {
+    let mut map = Map::new();
+    map.insert("sent".to_string(), json!(date(Utc::now())));
+    map.insert("type".to_string(), json!(format!("{}.{}", NOTIFICATION_PREFIX, kind)));
+    map.insert("ns".to_string(), json!(PARTY_NS));
+    map.insert("party_id".to_string(), json!(party.id));
+    map.insert("revision".to_string(), json!(party.revision));
+    map
+}
+
+fn member_fields(map: &mut Map<String, Value>, member: &PartyMember) {
+    map.insert("account_id".to_string(), json!(member.account_id));
+    map.insert("account_dn".to_string(), json!(member.display_name()));
+    map.insert("joined_at".to_string(), json!(date(member.joined_at)));
+    map.insert("updated_at".to_string(), json!(date(member.updated_at)));
+}
+
+fn notify_members(xmpp: &XmppHub, party: &Party, payload: Map<String, Value>) {
+    let payload = Value::Object(payload);
+    for member in &party.members {
+        xmpp.notify(&member.account_id, payload.clone());
+    }
+}
+
+pub fn notify_member_joined(xmpp: &XmppHub, party: &Party, account_id: &str) {
+    let Some(member) = party.member(account_id) else {
+        return;
+    };
+    let mut payload = notification("MEMBER_JOINED", party);
+    member_fields(&mut payload, member);
+    payload.insert("connection".to_string(), json!(member.connections.first()));
+    payload.insert("member_state_updated".to_string(), Value::Object(member.meta.clone()));
+    notify_members(xmpp, party, payload);
+}
+
+/// Tells the remaining members, and the member that left, that they're gone. A new captain is
+/// announced too if the captain left.
+pub fn notify_member_left(xmpp: &XmppHub, party_id: &str, departure: &PartyDeparture, kicked: bool) {
+    let kind = if kicked { "MEMBER_KICKED" } else { "MEMBER_LEFT" };
+    let mut payload = match &departure.party {
+        Some(party) => notification(kind, party),
+        None => {
+            let mut payload = Map::new();
+            payload.insert("sent".to_string(), json!(date(Utc::now())));
+            payload.insert("type".to_string(), json!(format!("{}.{}", NOTIFICATION_PREFIX, kind)));
+            payload.insert("ns".to_string(), json!(PARTY_NS));
+            payload.insert("party_id".to_string(), json!(party_id));
+            payload
+        }
+    };
+    payload.insert("account_id".to_string(), json!(departure.member.account_id));
+    payload.insert("member_state_updated".to_string(), json!({}));
+
+    xmpp.notify(&departure.member.account_id, Value::Object(payload.clone()));
+    if let Some(party) = &departure.party {
+        notify_members(xmpp, party, payload);
+        if departure.new_captain {
+            notify_new_captain(xmpp, party);
+        }
+    }
+}
+
+pub fn notify_new_captain(xmpp: &XmppHub, party: &Party) {
+    let Some(captain) = party.captain() else {
+        return;
+    };
+    let mut payload = notification("MEMBER_NEW_CAPTAIN", party);
+    member_fields(&mut payload, captain);
+    payload.insert("member_state_updated".to_string(), json!({}));
+    notify_members(xmpp, party, payload);
+}
+
+pub fn notify_party_updated(xmpp: &XmppHub, party: &Party, patch: &MetaPatch) {
+    let mut payload = notification("PARTY_UPDATED", party);
+    payload.insert("captain_id".to_string(), json!(party.captain().map(|captain| &captain.account_id)));
+    payload.insert("party_state_removed".to_string(), json!(patch.delete));
+    payload.insert("party_state_updated".to_string(), Value::Object(patch.update.clone()));
+    payload.insert("party_state_overridden".to_string(), json!({}));
+    payload.insert("party_privacy_type".to_string(), json!(party.config.get("joinability")));
+    payload.insert("party_type".to_string(), json!(party.config.get("type")));
+    payload.insert("party_sub_type".to_string(), json!(party.config.get("sub_type")));
+    payload.insert("max_number_of_members".to_string(), json!(party.max_size()));
+    payload.insert("invite_ttl_seconds".to_string(), json!(party.config.get("invite_ttl")));
+    payload.insert("created_at".to_string(), json!(date(party.created_at)));
+    payload.insert("updated_at".to_string(), json!(date(party.updated_at)));
+    notify_members(xmpp, party, payload);
+}
+
+pub fn notify_member_state_updated(xmpp: &XmppHub, party: &Party, account_id: &str, patch: &MetaPatch) {
+    let Some(member) = party.member(account_id) else {
+        return;
+    };
+    let mut payload = notification("MEMBER_STATE_UPDATED", party);
+    member_fields(&mut payload, member);
+    payload.insert("revision".to_string(), json!(member.revision));
+    payload.insert("member_state_removed".to_string(), json!(patch.delete));
+    payload.insert("member_state_updated".to_string(), Value::Object(patch.update.clone()));
+    payload.insert("member_state_overridden".to_string(), json!({}));
+    notify_members(xmpp, party, payload);
+}
+
+pub fn notify_invite(xmpp: &XmppHub, party: &Party, invite: &PartyInvite) {
+    let mut payload = notification("INITIAL_INVITE", party);
+    payload.insert("meta".to_string(), Value::Object(invite.meta.clone()));
+    payload.insert("inviter_id".to_string(), json!(invite.sent_by));
+    payload.insert("inviter_dn".to_string(), json!(party.member(&invite.sent_by).map(PartyMember::display_name)));
+    payload.insert("invitee_id".to_string(), json!(invite.sent_to));
+    payload.insert("sent_at".to_string(), json!(date(invite.sent_at)));
+    payload.insert("updated_at".to_string(), json!(date(invite.updated_at)));
+    payload.insert("friends_ids".to_string(), json!([]));
+    payload.insert("members_count".to_string(), json!(party.members.len()));
+    xmpp.notify(&invite.sent_to, Value::Object(payload));
+}
+
+pub fn notify_invite_declined(xmpp: &XmppHub, party: &Party, invite: &PartyInvite) {
+    let mut payload = notification("INVITE_DECLINED", party);
+    payload.insert("inviter_id".to_string(), json!(invite.sent_by));
+    payload.insert("invitee_id".to_string(), json!(invite.sent_to));
+    xmpp.notify(&invite.sent_by, Value::Object(payload));
+}
+
+pub fn notify_ping(xmpp: &XmppHub, ping: &PartyPing, pinger_dn: &str) {
+    xmpp.notify(&ping.sent_to, json!({
+        "sent": date(Utc::now()),
+        "type": format!("{}.PING", NOTIFICATION_PREFIX),
+        "ns": PARTY_NS,
+        "pinger_id": ping.sent_by,
+        "pinger_dn": pinger_dn,
+        "expires": date(ping.expires_at),
+        "meta": ping.meta,
+    }));
+}
@@ -0,0 +1,108 @@
+use crate::error;
+use crate::mongo::{GlyphMongo, ROLES_COLL, USER_DB};
+use bson::doc;
+use chrono::{DateTime, Utc};
+use mongodb::options::{IndexOptions, ReplaceOptions};
+use mongodb::IndexModel;
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+/// What a Discord user is allowed to do with the bot. Each role can do everything the ones
+/// below it can.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, poise::ChoiceParameter)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    User,
+    Moderator,
+    Admin,
+    /// Only ever comes from `OWNER_IDS`, it can't be granted.
+    Owner,
+}
+
+impl Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let str = match self {
+            Role::User => "User",
+            Role::Moderator => "Moderator",
+            Role::Admin => "Admin",
+            Role::Owner => "Owner",
+        };
+        write!(f, "{}", str)
+    }
+}
+
+/// A role granted to a Discord user with the role commands.
+#[derive(Serialize, Deserialize)]
+pub struct RoleGrant {
+    pub(crate) discord_id: u64,
+    pub(crate) role: Role,
+    pub(crate) granted_by: u64,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub(crate) granted_at: DateTime<Utc>,
+}
+
+/// Reads the comma separated Discord IDs in `OWNER_IDS`. Invalid entries are skipped.
+pub fn owner_ids() -> Vec<u64> {
+    std::env::var("OWNER_IDS")
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|id| id.trim().parse().ok())
+        .collect()
+}
+
+/// Returns the Discord roles in `ADMIN_ROLE_ID` and `MODERATOR_ROLE_ID` along with the role they
+/// give. Either can be left unset.
+pub fn guild_role_mapping() -> Vec<(u64, Role)> {
+    [("ADMIN_ROLE_ID", Role::Admin), ("MODERATOR_ROLE_ID", Role::Moderator)]
+        .into_iter()
+        .filter_map(|(var, role)| Some((std::env::var(var).ok()?.trim().parse().ok()?, role)))
+        .collect()
+}
+
+pub async fn create_indexes(mongo: &GlyphMongo) -> error::Result<()> {
+    let role_collection = mongo.collection::<RoleGrant>(USER_DB, ROLES_COLL).await;
+    role_collection.create_index(
+        IndexModel::builder()
+            .keys(doc! { "discord_id": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build(),
+    ).await?;
+    Ok(())
+}
+
+pub async fn get_grant(mongo: &GlyphMongo, discord_id: u64) -> error::Result<Option<RoleGrant>> {
+    let role_collection = mongo.collection::<RoleGrant>(USER_DB, ROLES_COLL).await;
+    let found = role_collection.find_one(doc! { "discord_id": discord_id as i64 }).await?;
+    Ok(found)
+}
+
+/// Returns the role a Discord user has from `OWNER_IDS` and the granted roles. Guild roles aren't
+/// known here, see [guild_role_mapping].
+pub async fn get_role(mongo: &GlyphMongo, discord_id: u64) -> error::Result<Role> {
+    if owner_ids().contains(&discord_id) {
+        return Ok(Role::Owner);
+    }
+    Ok(get_grant(mongo, discord_id).await?.map_or(Role::User, |grant| grant.role))
+}
+
+/// Replaces whatever role the Discord user was granted before.
+pub async fn grant_role(mongo: &GlyphMongo, discord_id: u64, role: Role, granted_by: u64) -> error::Result<()> {
+    let role_collection = mongo.collection::<RoleGrant>(USER_DB, ROLES_COLL).await;
+    let grant = RoleGrant {
+        discord_id,
+        role,
+        granted_by,
+        granted_at: Utc::now(),
+    };
+    role_collection.replace_one(doc! { "discord_id": discord_id as i64 }, grant)
+        .with_options(ReplaceOptions::builder().upsert(true).build())
+        .await?;
+    Ok(())
+}
+
+/// Returns false if the Discord user had no granted role.
+pub async fn revoke_role(mongo: &GlyphMongo, discord_id: u64) -> error::Result<bool> {
+    let role_collection = mongo.collection::<RoleGrant>(USER_DB, ROLES_COLL).await;
+    let result = role_collection.delete_one(doc! { "discord_id": discord_id as i64 }).await?;
+    Ok(result.deleted_count > 0)
+}
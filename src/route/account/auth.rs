@@ -4,14 +4,18 @@ use std::str::FromStr;
 use std::sync::Arc;
 use crate::{epic, serializers, user, util, GlyphState};
 use axum::{Form, Json};
-use axum::extract::State;
+use axum::extract::{Path, Query, State};
 use axum::http::{HeaderMap, StatusCode};
-use chrono::{TimeDelta, Utc};
+use chrono::{SecondsFormat, TimeDelta, Utc};
 use log::{error, warn};
 use serde::Deserialize;
 use uuid::Uuid;
-use crate::auth_manager::{OAuthManager};
+use crate::auth_manager::{OAuthManager, RefreshTokenUse};
+use crate::epic::epic_error::{to_response, EpicError, EpicErrorResponse};
+use crate::route::bearer::Bearer;
+use crate::user::BanRecord;
 use crate::util::UuidString;
+use crate::xmpp::session::close_revoked;
 
 #[derive(Debug, PartialEq, Deserialize)]
 pub enum GrantType {
@@ -104,16 +108,61 @@ pub enum OAuthResponse {
     Err(epic::epic_error::ErrorResponse),
 }
 
-fn internal_server_err() -> (StatusCode, Option<HeaderMap>, Json<OAuthResponse>) {
-    let err = epic::epic_error::make_epic_err(
+#[derive(serde::Serialize)]
+pub struct VerifyResponse {
+    token: String,
+    session_id: String,
+    token_type: String,
+    client_id: String,
+    internal_client: bool,
+    client_service: String,
+    account_id: String,
+    expires_in: i64,
+    #[serde(serialize_with = "serializers::serialize_datetime")]
+    expires_at: chrono::DateTime<Utc>,
+    auth_method: String,
+    display_name: String,
+    app: String,
+    in_app_id: String,
+    device_id: String,
+}
+
+#[derive(Deserialize)]
+pub struct KillSessionsQuery {
+    #[serde(rename = "killType")]
+    kill_type: String,
+}
+
+fn internal_server_err_body() -> EpicError {
+    epic::epic_error::make_epic_err(
         "errors.com.epicgames.common.internal_server_error",
         "Something went wrong",
-        &*vec![],
+        &[],
         -1,
         StatusCode::INTERNAL_SERVER_ERROR,
-    );
+    )
+}
 
-    (err.0, err.1, Json(OAuthResponse::Err { 0: err.2 }))
+fn internal_server_err() -> (StatusCode, Option<HeaderMap>, Json<OAuthResponse>) {
+    let err = internal_server_err_body();
+    (err.0, err.1, Json(OAuthResponse::Err(err.2)))
+}
+
+/// The error returned when a banned user tries to sign in. The reason and the end of the ban, or
+/// `never` for permanent bans, are sent as the message vars.
+fn account_not_active(ban: &BanRecord) -> (StatusCode, Option<HeaderMap>, Json<OAuthResponse>) {
+    let expires = match ban.expires_at {
+        Some(expires_at) => expires_at.to_rfc3339_opts(SecondsFormat::Millis, true),
+        None => "never".to_string(),
+    };
+    let err = epic::epic_error::make_epic_err(
+        "errors.com.epicgames.account.account_not_active",
+        &format!("Sorry, your account is banned until {}. Reason: {}", expires, ban.reason),
+        &[ban.reason.clone(), expires],
+        18006,
+        StatusCode::FORBIDDEN,
+    );
+    (err.0, err.1, Json(OAuthResponse::Err(err.2)))
 }
 
 pub async fn oauth(
@@ -125,7 +174,7 @@ pub async fn oauth(
         warn!("Auth token type was not `eg1`")
     }
 
-    let client_id = match util::extract_client_id(&headers) {
+    let (client_id, client_secret) = match util::extract_client_credentials(&headers) {
         Ok(val) => val,
         Err(_) => {
             let err = epic::epic_error::make_epic_err(
@@ -141,17 +190,33 @@ pub async fn oauth(
     };
 
     match form.grant_type {
-        GrantType::ClientCredentials => { client_credentials_oauth(state, client_id) }
+        GrantType::ClientCredentials => { client_credentials_oauth(state, client_id, &client_secret) }
         GrantType::ExchangeCode => { exchange_code_auth(state, form, client_id).await }
         GrantType::RefreshToken => { refresh_token_auth(state, form, client_id).await }
         GrantType::Password => { password_oauth() }
     }
 }
 
+/// Issues a client token. Tokens for the game server client in `SERVER_CLIENT_ID` can manage
+/// game servers, so that client also has to send the secret in `SERVER_CLIENT_SECRET`.
 fn client_credentials_oauth(
     state: Arc<GlyphState>,
     client_id: String,
+    client_secret: &str,
 ) -> (StatusCode, Option<HeaderMap>, Json<OAuthResponse>) {
+    let is_server_client = std::env::var("SERVER_CLIENT_ID").is_ok_and(|server_client_id| server_client_id == client_id);
+    if is_server_client && std::env::var("SERVER_CLIENT_SECRET").ok().as_deref() != Some(client_secret) {
+        let err = epic::epic_error::make_epic_err(
+            "errors.com.epicgames.common.oauth.invalid_client",
+            "Sorry the client credentials you are using are invalid",
+            &[],
+            1011,
+            StatusCode::UNAUTHORIZED,
+        );
+
+        return (err.0, err.1, Json(OAuthResponse::Err(err.2)));
+    }
+
     let token = match state.auth_manager.make_client_token(&client_id) {
         Ok(val) => val,
         Err(e) => {
@@ -191,18 +256,38 @@ async fn exchange_code_auth(
     };
     let supplied_code = supplied_code.replace("eg1~", "");
 
-    let exchange_code = match OAuthManager::get_exchange_code(&state.mongo, &supplied_code).await {
+    let exchange_code = match OAuthManager::redeem_exchange_code(&state.mongo, &supplied_code, &client_id).await {
         Ok(Some(val)) => val,
         Ok(None) => {
+            // Codes bound to another client aren't redeemed, so tell the client why.
+            match OAuthManager::exchange_code_client(&state.mongo, &supplied_code).await {
+                Ok(Some(_)) => {
+                    let err = epic::epic_error::make_epic_err(
+                        "errors.com.epicgames.common.oauth.unauthorized_client",
+                        &format!("Sorry the client '{}' is not allowed to redeem this exchange code", client_id),
+                        std::slice::from_ref(&client_id),
+                        1015,
+                        StatusCode::BAD_REQUEST,
+                    );
+
+                    return (err.0, err.1, Json(OAuthResponse::Err(err.2)));
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    error!("Failed to check exchange code validity: {}", e);
+                    return internal_server_err();
+                }
+            }
+
             let err = epic::epic_error::make_epic_err(
                 "errors.com.epicgames.account.oauth.exchange_code_not_found",
                 "Sorry the exchange code you supplied was not found. It is possible that it was no longer valid",
-                &*vec![],
+                &[],
                 18057,
                 StatusCode::UNAUTHORIZED,
             );
 
-            return (err.0, err.1, Json(OAuthResponse::Err { 0: err.2 }));
+            return (err.0, err.1, Json(OAuthResponse::Err(err.2)));
         }
         Err(e) => {
             error!("Failed to check exchange code validity: {}", e);
@@ -210,6 +295,18 @@ async fn exchange_code_auth(
         }
     };
 
+    if exchange_code.expires_at <= Utc::now() {
+        let err = epic::epic_error::make_epic_err(
+            "errors.com.epicgames.account.oauth.expired_exchange_code_session",
+            "Sorry the originating session for the exchange_code has expired.",
+            &[],
+            18128,
+            StatusCode::BAD_REQUEST,
+        );
+
+        return (err.0, err.1, Json(OAuthResponse::Err(err.2)));
+    }
+
     let user = match user::get_user(&state.mongo, &exchange_code.account_id).await {
         Ok(Some(val)) => val,
         Ok(None) => unreachable!("An account should exist if an exchange code exists"),
@@ -219,15 +316,20 @@ async fn exchange_code_auth(
         }
     };
 
+    if let Some(ban) = user.active_ban() {
+        return account_not_active(ban);
+    }
+
     let device_id = Uuid::new_v4().to_stripped_string();
-    let access_token = match state.auth_manager.make_access_token(&state.mongo, &user, &client_id, &device_id, &form.grant_type, None).await {
+    let family_id = Uuid::new_v4().to_stripped_string();
+    let access_token = match state.auth_manager.make_access_token(&state.mongo, &user, &client_id, &device_id, &family_id, &form.grant_type, None).await {
         Ok(val) => val,
         Err(e) => {
             error!("Failed to make an access token: {}", e);
             return internal_server_err();
         }
     };
-    let refresh_token = match state.auth_manager.make_refresh_token(&state.mongo, &user, &client_id, &device_id, &form.grant_type, None).await {
+    let refresh_token = match state.auth_manager.make_refresh_token(&state.mongo, &user, &client_id, &device_id, &family_id, &form.grant_type, None).await {
         Ok(val) => val,
         Err(e) => {
             error!("Failed to make a refresh token: {}", e);
@@ -273,19 +375,39 @@ async fn refresh_token_auth(
         return (err.0, err.1, Json(OAuthResponse::Err { 0: err.2 }));
     };
 
-    let supplied_token = match OAuthManager::get_refresh_token(&state.mongo, &supplied_code.replace("eg1~", "")).await {
-        Ok(Some(val)) => val,
-        Ok(None) => {
-            let err = epic::epic_error::make_epic_err(
-                "errors.com.epicgames.account.auth_token.invalid_refresh_token",
-                "Sorry the refresh token '${refresh_token}' is invalid",
-                &*vec![],
-                18036,
-                StatusCode::BAD_REQUEST,
-            );
+    let supplied_code = supplied_code.replace("eg1~", "");
+    let invalid_refresh_token = || {
+        let err = epic::epic_error::make_epic_err(
+            "errors.com.epicgames.account.auth_token.invalid_refresh_token",
+            &format!("Sorry the refresh token '{}' is invalid", supplied_code),
+            std::slice::from_ref(&supplied_code),
+            18036,
+            StatusCode::BAD_REQUEST,
+        );
 
-            return (err.0, err.1, Json(OAuthResponse::Err { 0: err.2 }));
+        (err.0, err.1, Json(OAuthResponse::Err(err.2)))
+    };
+
+    if state.auth_manager.verify_token(&supplied_code).is_err() {
+        return invalid_refresh_token();
+    }
+
+    let supplied_token = match OAuthManager::use_refresh_token(&state.mongo, &supplied_code, &client_id).await {
+        Ok(RefreshTokenUse::Valid(val)) => val,
+        Ok(RefreshTokenUse::Reused(val)) => {
+            // A refresh token is only ever sent twice if it was leaked, so nothing issued from
+            // the same sign in can be trusted anymore.
+            warn!("Refresh token for account {} was reused, revoking its token family", val.account_id);
+            if let Some(family_id) = &val.family_id {
+                if let Err(e) = OAuthManager::kill_token_family(&state.mongo, family_id).await {
+                    error!("Failed to kill a token family: {}", e);
+                    return internal_server_err();
+                }
+            }
+            close_revoked(&state, &val.account_id.to_stripped_string()).await;
+            return invalid_refresh_token();
         }
+        Ok(RefreshTokenUse::Invalid) => return invalid_refresh_token(),
         Err(e) => {
             error!("Failed to check refresh token validity: {}", e);
             return internal_server_err();
@@ -301,15 +423,20 @@ async fn refresh_token_auth(
         }
     };
 
-    let device_id = Uuid::new_v4().to_stripped_string();
-    let access_token = match state.auth_manager.make_access_token(&state.mongo, &user, &client_id, &device_id, &form.grant_type, None).await {
+    if let Some(ban) = user.active_ban() {
+        return account_not_active(ban);
+    }
+
+    let device_id = supplied_token.device_id.unwrap_or_else(|| Uuid::new_v4().to_stripped_string());
+    let family_id = supplied_token.family_id.unwrap_or_else(|| Uuid::new_v4().to_stripped_string());
+    let access_token = match state.auth_manager.make_access_token(&state.mongo, &user, &client_id, &device_id, &family_id, &form.grant_type, None).await {
         Ok(val) => val,
         Err(e) => {
             error!("Failed to make an access token: {}", e);
             return internal_server_err();
         }
     };
-    let refresh_token = match state.auth_manager.make_refresh_token(&state.mongo, &user, &client_id, &device_id, &form.grant_type, None).await {
+    let refresh_token = match state.auth_manager.make_refresh_token(&state.mongo, &user, &client_id, &device_id, &family_id, &form.grant_type, None).await {
         Ok(val) => val,
         Err(e) => {
             error!("Failed to make a refresh token: {}", e);
@@ -349,3 +476,103 @@ fn password_oauth() -> (StatusCode, Option<HeaderMap>, Json<OAuthResponse>) {
 
     (err.0, err.1, Json(OAuthResponse::PasswordResponse { 0: err.2 }))
 }
+
+/// Describes the session the supplied access token belongs to, using the same values that were
+/// put into its claims by [OAuthManager::make_access_token].
+pub async fn verify(
+    bearer: Bearer,
+) -> (StatusCode, Json<VerifyResponse>) {
+    let claim = |name: &str| bearer.claims.get(name).cloned().unwrap_or_default();
+    let response = VerifyResponse {
+        token: format!("eg1~{}", bearer.token.token),
+        session_id: claim("jti"),
+        token_type: "bearer".to_string(),
+        client_id: claim("clid"),
+        internal_client: true,
+        client_service: claim("clsvc"),
+        account_id: claim("sub"),
+        expires_in: bearer.token.expires_at.sub(Utc::now()).num_seconds(),
+        expires_at: bearer.token.expires_at,
+        auth_method: claim("am"),
+        display_name: claim("dn"),
+        app: claim("app"),
+        in_app_id: claim("iai"),
+        device_id: claim("dvid"),
+    };
+
+    (StatusCode::OK, Json(response))
+}
+
+/// Kills a single access token belonging to the signed in user.
+pub async fn kill_session(
+    State(state): State<Arc<GlyphState>>,
+    bearer: Bearer,
+    Path(token): Path<String>,
+) -> Result<StatusCode, EpicErrorResponse> {
+    let token = token.replace("eg1~", "");
+    let unknown_session = || to_response(epic::epic_error::make_epic_err(
+        "errors.com.epicgames.account.auth_token.unknown_oauth_session",
+        &format!("Sorry we could not find the auth session '{}'", token),
+        std::slice::from_ref(&token),
+        18051,
+        StatusCode::NOT_FOUND,
+    ));
+
+    match OAuthManager::get_access_token(&state.mongo, &token).await {
+        Ok(Some(val)) if val.account_id == bearer.user.account_id => {}
+        Ok(_) => return Err(unknown_session()),
+        Err(e) => {
+            error!("Failed to check access token validity: {}", e);
+            return Err(to_response(internal_server_err_body()));
+        }
+    }
+
+    match OAuthManager::kill_access_token(&state.mongo, &token).await {
+        Ok(true) => {
+            close_revoked(&state, &bearer.user.account_id.to_stripped_string()).await;
+            Ok(StatusCode::NO_CONTENT)
+        }
+        Ok(false) => Err(unknown_session()),
+        Err(e) => {
+            error!("Failed to kill an access token: {}", e);
+            Err(to_response(internal_server_err_body()))
+        }
+    }
+}
+
+/// Kills every session belonging to the signed in user, or every session other than the one
+/// making the request for the `OTHERS` kill types.
+pub async fn kill_sessions(
+    State(state): State<Arc<GlyphState>>,
+    bearer: Bearer,
+    Query(query): Query<KillSessionsQuery>,
+) -> Result<StatusCode, EpicErrorResponse> {
+    let result = match query.kill_type.as_str() {
+        "ALL" | "ALL_ACCOUNT_CLIENT" => {
+            OAuthManager::kill_user_tokens(&state.mongo, &bearer.user).await.map(|_| ())
+        }
+        "OTHERS" | "OTHERS_ACCOUNT_CLIENT" | "OTHERS_ACCOUNT_CLIENT_SERVICE" => {
+            OAuthManager::kill_other_sessions(&state.mongo, &bearer.user, &bearer.token).await
+        }
+        _ => {
+            return Err(to_response(epic::epic_error::make_epic_err(
+                "errors.com.epicgames.common.oauth.invalid_request",
+                &format!("Unknown kill type {}", query.kill_type),
+                std::slice::from_ref(&query.kill_type),
+                1013,
+                StatusCode::BAD_REQUEST,
+            )));
+        }
+    };
+
+    match result {
+        Ok(()) => {
+            close_revoked(&state, &bearer.user.account_id.to_stripped_string()).await;
+            Ok(StatusCode::NO_CONTENT)
+        }
+        Err(e) => {
+            error!("Failed to kill sessions: {}", e);
+            Err(to_response(internal_server_err_body()))
+        }
+    }
+}
@@ -0,0 +1,258 @@
+use crate::auth_manager::OAuthManager;
+use crate::epic::epic_error::{make_epic_err, to_response, EpicErrorResponse};
+use crate::error::Error;
+use crate::route::bearer::Bearer;
+use crate::user::User;
+use crate::util::UuidString;
+use crate::xmpp::session::close_revoked;
+use crate::{user, GlyphState};
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::Json;
+use log::error;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// The most accounts that can be looked up with one bulk request.
+const MAX_BULK_ACCOUNTS: usize = 100;
+
+fn format_date(date: chrono::DateTime<chrono::Utc>) -> String {
+    date.to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+}
+
+/// The account details a user can see about their own account.
+#[derive(Serialize)]
+pub struct AccountInfo {
+    id: String,
+    #[serde(rename = "displayName")]
+    display_name: String,
+    #[serde(rename = "numberOfDisplayNameChanges")]
+    number_of_display_name_changes: usize,
+    #[serde(rename = "lastDisplayNameChange", skip_serializing_if = "Option::is_none")]
+    last_display_name_change: Option<String>,
+    #[serde(rename = "canUpdateDisplayName")]
+    can_update_display_name: bool,
+    #[serde(rename = "canUpdateDisplayNameNext", skip_serializing_if = "Option::is_none")]
+    can_update_display_name_next: Option<String>,
+    #[serde(rename = "lastLogin")]
+    last_login: String,
+    #[serde(rename = "preferredLanguage")]
+    preferred_language: String,
+    #[serde(rename = "tfaEnabled")]
+    tfa_enabled: bool,
+    #[serde(rename = "emailVerified")]
+    email_verified: bool,
+}
+
+impl From<&User> for AccountInfo {
+    fn from(user: &User) -> Self {
+        let next_change = user.next_display_name_change();
+        Self {
+            id: user.account_id.to_stripped_string(),
+            display_name: user.display_name.clone(),
+            number_of_display_name_changes: user.name_history.len(),
+            last_display_name_change: user.name_history.iter().map(|entry| entry.changed_at).max().map(format_date),
+            can_update_display_name: next_change.is_none(),
+            can_update_display_name_next: next_change.map(format_date),
+            last_login: format_date(user.last_login),
+            preferred_language: "en".to_string(),
+            tfa_enabled: false,
+            email_verified: true,
+        }
+    }
+}
+
+/// What anyone can see about an account.
+#[derive(Serialize)]
+pub struct PublicAccount {
+    id: String,
+    #[serde(rename = "displayName")]
+    display_name: String,
+    #[serde(rename = "externalAuths")]
+    external_auths: Map<String, Value>,
+}
+
+impl From<&User> for PublicAccount {
+    fn from(user: &User) -> Self {
+        Self {
+            id: user.account_id.to_stripped_string(),
+            display_name: user.display_name.clone(),
+            external_auths: Map::new(),
+        }
+    }
+}
+
+/// The signed in user gets their own details, everyone else only gets the public ones.
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum AccountResponse {
+    Own(AccountInfo),
+    Public(PublicAccount),
+}
+
+#[derive(Deserialize)]
+pub struct DisplayNameUpdate {
+    #[serde(rename = "displayName")]
+    display_name: String,
+}
+
+fn account_not_found(account: &str) -> EpicErrorResponse {
+    to_response(make_epic_err(
+        "errors.com.epicgames.account.account_not_found",
+        &format!("Sorry, we couldn't find an account for {}", account),
+        &[account.to_string()],
+        18007,
+        StatusCode::NOT_FOUND,
+    ))
+}
+
+fn internal_server_err() -> EpicErrorResponse {
+    to_response(make_epic_err(
+        "errors.com.epicgames.common.internal_server_error",
+        "Something went wrong",
+        &[],
+        -1,
+        StatusCode::INTERNAL_SERVER_ERROR,
+    ))
+}
+
+/// Turns an error from [user::change_display_name] into the matching Epic error.
+fn display_name_err(err: Error) -> EpicErrorResponse {
+    match err {
+        Error::InvalidDisplayName(_) => to_response(make_epic_err(
+            "errors.com.epicgames.validation.validation_failed",
+            &format!("Validation Failed. {}", err),
+            &[],
+            1040,
+            StatusCode::BAD_REQUEST,
+        )),
+        Error::DisplayNameTaken(ref name) => to_response(make_epic_err(
+            "errors.com.epicgames.account.duplicate_display_name",
+            &format!("Sorry, the display name {} is already in use", name),
+            std::slice::from_ref(name),
+            18006,
+            StatusCode::CONFLICT,
+        )),
+        Error::DisplayNameCooldown(next_change) => to_response(make_epic_err(
+            "errors.com.epicgames.account.display_name_change_cooldown",
+            &format!("Sorry, you can't change your display name again until {}", format_date(next_change)),
+            &[format_date(next_change)],
+            18124,
+            StatusCode::FORBIDDEN,
+        )),
+        _ => {
+            error!("Failed to change a display name: {}", err);
+            internal_server_err()
+        }
+    }
+}
+
+/// Changes the display name of the signed in user. Every token they hold still carries the old
+/// name, so they're all killed and the client has to sign in again.
+pub async fn update_display_name(
+    State(state): State<Arc<GlyphState>>,
+    bearer: Bearer,
+    Path(account_id): Path<String>,
+    Json(update): Json<DisplayNameUpdate>,
+) -> Result<(StatusCode, Json<AccountInfo>), EpicErrorResponse> {
+    bearer.require_account(&account_id)?;
+
+    let updated = user::change_display_name(&state.mongo, &bearer.user, update.display_name).await
+        .map_err(display_name_err)?;
+    if updated.display_name == bearer.user.display_name {
+        return Ok((StatusCode::OK, Json(AccountInfo::from(&updated))));
+    }
+    if let Err(e) = OAuthManager::kill_user_tokens(&state.mongo, &updated).await {
+        error!("Failed to kill tokens after a display name change: {}", e);
+    }
+    close_revoked(&state, &updated.account_id.to_stripped_string()).await;
+
+    Ok((StatusCode::OK, Json(AccountInfo::from(&updated))))
+}
+
+async fn find_account(state: &GlyphState, account_id: &str) -> Result<User, EpicErrorResponse> {
+    let Ok(uuid) = Uuid::parse_str(account_id) else {
+        return Err(account_not_found(account_id));
+    };
+    match user::get_user(&state.mongo, &uuid).await {
+        Ok(Some(val)) => Ok(val),
+        Ok(None) => Err(account_not_found(account_id)),
+        Err(e) => {
+            error!("Failed to get a user: {}", e);
+            Err(internal_server_err())
+        }
+    }
+}
+
+pub async fn get_account(
+    State(state): State<Arc<GlyphState>>,
+    bearer: Bearer,
+    Path(account_id): Path<String>,
+) -> Result<Json<AccountResponse>, EpicErrorResponse> {
+    let user = find_account(&state, &account_id).await?;
+    if user.account_id == bearer.user.account_id {
+        return Ok(Json(AccountResponse::Own(AccountInfo::from(&user))));
+    }
+    Ok(Json(AccountResponse::Public(PublicAccount::from(&user))))
+}
+
+/// Looks up every account in the repeated `accountId` query parameter. Unknown and malformed IDs
+/// are left out of the response instead of failing the whole lookup.
+pub async fn get_accounts(
+    State(state): State<Arc<GlyphState>>,
+    _bearer: Bearer,
+    Query(query): Query<Vec<(String, String)>>,
+) -> Result<Json<Vec<PublicAccount>>, EpicErrorResponse> {
+    let account_ids = query.iter()
+        .filter(|(key, _)| key == "accountId")
+        .map(|(_, value)| value)
+        .collect::<Vec<_>>();
+    if account_ids.is_empty() || account_ids.len() > MAX_BULK_ACCOUNTS {
+        return Err(to_response(make_epic_err(
+            "errors.com.epicgames.account.invalid_account_id_count",
+            &format!("Sorry, the number of account id should be at least one and not more than {}.", MAX_BULK_ACCOUNTS),
+            &[MAX_BULK_ACCOUNTS.to_string()],
+            18066,
+            StatusCode::BAD_REQUEST,
+        )));
+    }
+
+    let uuids = account_ids.iter()
+        .filter_map(|id| Uuid::parse_str(id).ok())
+        .collect::<Vec<_>>();
+    match user::get_users(&state.mongo, &uuids).await {
+        Ok(users) => Ok(Json(users.iter().map(PublicAccount::from).collect())),
+        Err(e) => {
+            error!("Failed to get users: {}", e);
+            Err(internal_server_err())
+        }
+    }
+}
+
+pub async fn get_account_by_display_name(
+    State(state): State<Arc<GlyphState>>,
+    _bearer: Bearer,
+    Path(display_name): Path<String>,
+) -> Result<Json<PublicAccount>, EpicErrorResponse> {
+    match user::get_user_by_display_name(&state.mongo, &display_name).await {
+        Ok(Some(val)) => Ok(Json(PublicAccount::from(&val))),
+        Ok(None) => Err(account_not_found(&display_name)),
+        Err(e) => {
+            error!("Failed to get a user: {}", e);
+            Err(internal_server_err())
+        }
+    }
+}
+
+/// Glyph accounts are only ever linked to Discord, which the client has no use for, so this is
+/// always empty for accounts that exist.
+pub async fn get_external_auths(
+    State(state): State<Arc<GlyphState>>,
+    _bearer: Bearer,
+    Path(account_id): Path<String>,
+) -> Result<Json<Vec<Value>>, EpicErrorResponse> {
+    find_account(&state, &account_id).await?;
+    Ok(Json(vec![]))
+}
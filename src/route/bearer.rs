@@ -0,0 +1,159 @@
+use crate::auth_manager::{OAuthManager, OAuthToken};
+use crate::epic::epic_error::{make_epic_err, to_response, EpicErrorResponse};
+use crate::user::User;
+use crate::util::UuidString;
+use crate::{user, GlyphState};
+use axum::async_trait;
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use axum::http::{HeaderMap, StatusCode};
+use log::error;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// An extractor for routes that require a signed in user. It verifies the
+/// `Authorization: bearer eg1~...` header against the signing keys and the stored access tokens
+/// and loads the [User] the token belongs to.
+pub struct Bearer {
+    pub(crate) user: User,
+    pub(crate) token: OAuthToken,
+    pub(crate) claims: BTreeMap<String, String>,
+}
+
+fn verification_failed(token: &str) -> EpicErrorResponse {
+    to_response(make_epic_err(
+        "errors.com.epicgames.common.authentication.token_verification_failed",
+        &format!("Sorry we couldn't validate your token {}. Please try with a new token.", token),
+        &[token.to_string()],
+        1014,
+        StatusCode::UNAUTHORIZED,
+    ))
+}
+
+fn internal_server_err() -> EpicErrorResponse {
+    to_response(make_epic_err(
+        "errors.com.epicgames.common.internal_server_error",
+        "Something went wrong",
+        &[],
+        -1,
+        StatusCode::INTERNAL_SERVER_ERROR,
+    ))
+}
+
+/// Returns the raw token from an `Authorization` header, without the `bearer` scheme or the
+/// `eg1~` prefix.
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    let header = headers.get("Authorization")?.to_str().ok()?;
+    let (scheme, token) = header.split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("bearer") {
+        return None;
+    }
+    Some(token.trim().trim_start_matches("eg1~"))
+}
+
+impl Bearer {
+    /// Makes sure the account ID from the request path belongs to the signed in user.
+    pub fn require_account(&self, account_id: &str) -> Result<(), EpicErrorResponse> {
+        if account_id == self.user.account_id.to_stripped_string() {
+            return Ok(());
+        }
+        Err(to_response(make_epic_err(
+            "errors.com.epicgames.common.authentication.authentication_failed",
+            &format!("Authentication failed for account {}", account_id),
+            &[account_id.to_string()],
+            1032,
+            StatusCode::FORBIDDEN,
+        )))
+    }
+
+    /// Checks a raw access token, without the `eg1~` prefix, the same way the extractor does.
+    /// Used where the token doesn't come from an `Authorization` header.
+    pub async fn verify(state: &GlyphState, raw_token: &str) -> Result<Bearer, EpicErrorResponse> {
+        let claims = match state.auth_manager.verify_token(raw_token) {
+            Ok(val) => val,
+            Err(_) => return Err(verification_failed(raw_token)),
+        };
+
+        let token = match OAuthManager::get_access_token(&state.mongo, raw_token).await {
+            Ok(Some(val)) => val,
+            Ok(None) => return Err(verification_failed(raw_token)),
+            Err(e) => {
+                error!("Failed to check access token validity: {}", e);
+                return Err(internal_server_err());
+            }
+        };
+
+        let user = match user::get_user(&state.mongo, &token.account_id).await {
+            Ok(Some(val)) => val,
+            Ok(None) => return Err(verification_failed(raw_token)),
+            Err(e) => {
+                error!("Failed to get a user: {}", e);
+                return Err(internal_server_err());
+            }
+        };
+
+        if user.active_ban().is_some() {
+            return Err(verification_failed(raw_token));
+        }
+
+        Ok(Bearer { user, token, claims })
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<Arc<GlyphState>> for Bearer {
+    type Rejection = EpicErrorResponse;
+
+    async fn from_request_parts(parts: &mut Parts, state: &Arc<GlyphState>) -> Result<Self, Self::Rejection> {
+        let Some(raw_token) = bearer_token(&parts.headers) else {
+            return Err(to_response(make_epic_err(
+                "errors.com.epicgames.common.authorization.authorization_failed",
+                "Authorization failed. The Authorization header is missing or isn't a bearer token.",
+                &[],
+                1032,
+                StatusCode::UNAUTHORIZED,
+            )));
+        };
+
+        Bearer::verify(state, raw_token).await
+    }
+}
+
+/// An extractor for the server API. Dedicated servers sign in with the client credentials grant
+/// using the client ID in `SERVER_CLIENT_ID` and the secret in `SERVER_CLIENT_SECRET`, so no server
+/// API works while they aren't set.
+pub struct GameServerAuth {
+    pub(crate) client_id: String,
+}
+
+#[async_trait]
+impl FromRequestParts<Arc<GlyphState>> for GameServerAuth {
+    type Rejection = EpicErrorResponse;
+
+    async fn from_request_parts(parts: &mut Parts, state: &Arc<GlyphState>) -> Result<Self, Self::Rejection> {
+        let Some(raw_token) = bearer_token(&parts.headers) else {
+            return Err(to_response(make_epic_err(
+                "errors.com.epicgames.common.authorization.authorization_failed",
+                "Authorization failed. The Authorization header is missing or isn't a bearer token.",
+                &[],
+                1032,
+                StatusCode::UNAUTHORIZED,
+            )));
+        };
+
+        let claims = state.auth_manager.verify_token(raw_token).map_err(|_| verification_failed(raw_token))?;
+        let client_id = claims.get("clid").cloned().unwrap_or_default();
+        let server_client_id = std::env::var("SERVER_CLIENT_ID").ok();
+        if claims.get("am").map(String::as_str) != Some("client_credentials") || server_client_id.as_ref() != Some(&client_id) {
+            return Err(to_response(make_epic_err(
+                "errors.com.epicgames.common.missing_permission",
+                &format!("Sorry the client '{}' is not allowed to manage game servers", client_id),
+                &[client_id.to_string()],
+                1023,
+                StatusCode::FORBIDDEN,
+            )));
+        }
+
+        Ok(GameServerAuth { client_id })
+    }
+}
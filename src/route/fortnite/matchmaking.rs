@@ -0,0 +1,223 @@
+use crate::epic::epic_error::{make_epic_err, to_response, EpicErrorResponse};
+use crate::matchmaking::matchmaker::parse_bucket_id;
+use crate::route::bearer::Bearer;
+use crate::util::UuidString;
+use crate::GlyphState;
+use axum::extract::{Path, Query, State};
+use axum::http::header::HOST;
+use axum::http::{HeaderMap, StatusCode};
+use axum::Json;
+use chrono::{SecondsFormat, Utc};
+use log::error;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use uuid::Uuid;
+
+#[derive(Deserialize)]
+pub struct TicketQuery {
+    #[serde(rename = "bucketId")]
+    bucket_id: String,
+    /// A comma separated list of everyone in the party, sent by the party captain. Only members of
+    /// the captain's party are kept, see [party_player_ids].
+    #[serde(rename = "partyPlayerIds")]
+    party_player_ids: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct TicketResponse {
+    #[serde(rename = "serviceUrl")]
+    service_url: String,
+    #[serde(rename = "ticketType")]
+    ticket_type: String,
+    payload: String,
+    signature: String,
+}
+
+#[derive(Serialize)]
+pub struct SessionKey {
+    #[serde(rename = "accountId")]
+    account_id: String,
+    #[serde(rename = "sessionId")]
+    session_id: String,
+    key: String,
+}
+
+fn session_not_found(session_id: &str) -> EpicErrorResponse {
+    to_response(make_epic_err(
+        "errors.com.epicgames.matchmaking.session_not_found",
+        &format!("Sorry, we couldn't find a session by id {}", session_id),
+        &[session_id.to_string()],
+        12101,
+        StatusCode::NOT_FOUND,
+    ))
+}
+
+/// Returns everyone the ticket matchmakes, starting with the account itself. The captain of a
+/// party brings along the requested IDs that are members of their party, up to the party's size,
+/// and anyone else only matchmakes themselves.
+fn party_player_ids(state: &GlyphState, account_id: &str, requested: Option<&str>) -> Vec<String> {
+    let mut party_player_ids = vec![account_id.to_string()];
+    let Some(party) = state.parties.party_of(account_id) else {
+        return party_player_ids;
+    };
+    if party.captain().is_none_or(|captain| captain.account_id != account_id) {
+        return party_player_ids;
+    }
+
+    for player_id in requested.iter().flat_map(|ids| ids.split(',')) {
+        if party_player_ids.len() >= party.max_size() {
+            break;
+        }
+        if party.member(player_id).is_some() && !party_player_ids.iter().any(|id| id == player_id) {
+            party_player_ids.push(player_id.to_string());
+        }
+    }
+    party_player_ids
+}
+
+/// Issues a signed ticket for the matchmaker at [crate::route::matchmaker::connect].
+pub async fn ticket(
+    State(state): State<Arc<GlyphState>>,
+    bearer: Bearer,
+    headers: HeaderMap,
+    Path(account_id): Path<String>,
+    Query(query): Query<TicketQuery>,
+) -> Result<Json<TicketResponse>, EpicErrorResponse> {
+    bearer.require_account(&account_id)?;
+    let Some((build_unique_id, region, playlist)) = parse_bucket_id(&query.bucket_id) else {
+        return Err(to_response(make_epic_err(
+            "errors.com.epicgames.fortnite.invalid_bucket_id",
+            &format!("Failed to parse bucketId: '{}'", query.bucket_id),
+            &[query.bucket_id.to_string()],
+            16102,
+            StatusCode::BAD_REQUEST,
+        )));
+    };
+
+    let party_player_ids = party_player_ids(&state, &account_id, query.party_player_ids.as_deref()).join(",");
+
+    let ticket_id = Uuid::new_v4().to_stripped_string();
+    let mut claims = BTreeMap::new();
+    claims.insert("jti", ticket_id.as_str());
+    claims.insert("accountId", account_id.as_str());
+    claims.insert("partyPlayerIds", party_player_ids.as_str());
+    claims.insert("buildUniqueId", build_unique_id);
+    claims.insert("region", region);
+    claims.insert("playlist", playlist);
+    let (payload, signature) = state.auth_manager.make_matchmaking_ticket(&claims).map_err(|e| {
+        error!("Failed to sign a matchmaking ticket: {}", e);
+        to_response(make_epic_err(
+            "errors.com.epicgames.common.internal_server_error",
+            "Something went wrong",
+            &[],
+            -1,
+            StatusCode::INTERNAL_SERVER_ERROR,
+        ))
+    })?;
+
+    let host = headers.get(HOST).and_then(|host| host.to_str().ok()).unwrap_or("127.0.0.1:5746");
+    Ok(Json(TicketResponse {
+        service_url: format!("ws://{}/matchmaking", host),
+        ticket_type: "mms-player".to_string(),
+        payload,
+        signature,
+    }))
+}
+
+/// Describes the session so the client can connect to the server hosting it.
+pub async fn get_session(
+    State(state): State<Arc<GlyphState>>,
+    bearer: Bearer,
+    Path(session_id): Path<String>,
+) -> Result<Json<Value>, EpicErrorResponse> {
+    let server = state.servers.session(&session_id).ok_or_else(|| session_not_found(&session_id))?;
+    let account_id = bearer.user.account_id.to_stripped_string();
+    let build_unique_id = state.servers.assignment(&account_id)
+        .map(|assignment| assignment.build_unique_id)
+        .unwrap_or_else(|| "0".to_string());
+    let open_slots = server.open_slots();
+    // Only players assigned to the session get the key, the same as [session_key].
+    let session_key = server.players.contains(&account_id).then_some(server.session_key.as_str());
+
+    let mut session = json!({
+        "id": server.session_id,
+        "ownerId": server.id,
+        "ownerName": format!("[DS]{}", server.id),
+        "serverName": format!("[DS]{}", server.id),
+        "serverAddress": server.ip,
+        "serverPort": server.port,
+        "maxPublicPlayers": server.max_players,
+        "openPublicPlayers": open_slots,
+        "maxPrivatePlayers": 0,
+        "openPrivatePlayers": 0,
+        "attributes": {
+            "REGION_s": server.region,
+            "GAMEMODE_s": "FORTATHENA",
+            "ALLOWBROADCASTING_b": true,
+            "SUBREGION_s": server.region,
+            "DCID_s": format!("FORTNITE-{}", server.region.to_uppercase()),
+            "tenant_s": "Fortnite",
+            "MATCHMAKINGPOOL_s": "Any",
+            "STORMSHIELDDEFENSETYPE_i": 0,
+            "HOTFIXVERSION_i": 0,
+            "PLAYLISTNAME_s": server.playlist,
+            "TENANT_s": "Fortnite",
+            "BEACONPORT_i": 15009,
+        },
+        "publicPlayers": [],
+        "privatePlayers": [],
+        "totalPlayers": server.players.len(),
+        "allowJoinInProgress": false,
+        "shouldAdvertise": false,
+        "isDedicated": false,
+        "usesStats": false,
+        "allowInvites": false,
+        "usesPresence": false,
+        "allowJoinViaPresence": true,
+        "allowJoinViaPresenceFriendsOnly": false,
+        "buildUniqueId": build_unique_id,
+        "lastUpdated": Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        "started": false,
+    });
+    if let Some(session_key) = session_key {
+        session["attributes"]["SESSIONKEY_s"] = json!(session_key);
+    }
+    Ok(Json(session))
+}
+
+pub async fn join_session(
+    State(state): State<Arc<GlyphState>>,
+    _bearer: Bearer,
+    Path(session_id): Path<String>,
+) -> Result<StatusCode, EpicErrorResponse> {
+    state.servers.session(&session_id).ok_or_else(|| session_not_found(&session_id))?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Returns the sessions the account is in. The client only uses this to rejoin, which isn't
+/// supported, so it is always empty.
+pub async fn find_player(
+    _bearer: Bearer,
+    Path(_account_id): Path<String>,
+) -> Json<Vec<Value>> {
+    Json(vec![])
+}
+
+/// Returns the key the client gives the server to prove it was assigned to the session.
+pub async fn session_key(
+    State(state): State<Arc<GlyphState>>,
+    bearer: Bearer,
+    Path((account_id, session_id)): Path<(String, String)>,
+) -> Result<Json<SessionKey>, EpicErrorResponse> {
+    bearer.require_account(&account_id)?;
+    let server = state.servers.session(&session_id)
+        .filter(|server| server.players.contains(&account_id))
+        .ok_or_else(|| session_not_found(&session_id))?;
+    Ok(Json(SessionKey {
+        account_id,
+        session_id,
+        key: server.session_key,
+    }))
+}
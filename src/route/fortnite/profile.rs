@@ -0,0 +1,167 @@
+use crate::epic::epic_error::{make_epic_err, EpicError, ErrorResponse};
+use crate::mcp::commands::{run_command, CommandOutput};
+use crate::mcp::defaults::get_or_create_profile;
+use crate::mcp::profile::{save_profile, ProfileChange, ProfileId, SAVE_ATTEMPTS};
+use crate::route::bearer::Bearer;
+use crate::util::UuidString;
+use crate::{serializers, GlyphState};
+use axum::body::Bytes;
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::Json;
+use chrono::Utc;
+use log::error;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::str::FromStr;
+use std::sync::Arc;
+use uuid::Uuid;
+
+#[derive(Deserialize)]
+pub struct ProfileQuery {
+    #[serde(rename = "profileId")]
+    profile_id: String,
+    rvn: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct ProfileUpdate {
+    #[serde(rename = "profileRevision")]
+    profile_revision: i64,
+    #[serde(rename = "profileId")]
+    profile_id: ProfileId,
+    #[serde(rename = "profileChangesBaseRevision")]
+    profile_changes_base_revision: i64,
+    #[serde(rename = "profileChanges")]
+    profile_changes: Vec<ProfileChange>,
+    #[serde(rename = "profileCommandRevision")]
+    profile_command_revision: i64,
+    #[serde(rename = "serverTime", serialize_with = "serializers::serialize_datetime")]
+    server_time: chrono::DateTime<Utc>,
+    #[serde(rename = "responseVersion")]
+    response_version: i32,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum ProfileResponse {
+    Update(ProfileUpdate),
+    Err(ErrorResponse),
+}
+
+fn epic_err(err: EpicError) -> (StatusCode, Option<HeaderMap>, Json<ProfileResponse>) {
+    (err.0, err.1, Json(ProfileResponse::Err(err.2)))
+}
+
+fn internal_server_err() -> (StatusCode, Option<HeaderMap>, Json<ProfileResponse>) {
+    epic_err(make_epic_err(
+        "errors.com.epicgames.common.internal_server_error",
+        "Something went wrong",
+        &[],
+        -1,
+        StatusCode::INTERNAL_SERVER_ERROR,
+    ))
+}
+
+pub async fn client_command(
+    State(state): State<Arc<GlyphState>>,
+    bearer: Bearer,
+    Path((account_id, command)): Path<(String, String)>,
+    Query(query): Query<ProfileQuery>,
+    body: Bytes,
+) -> (StatusCode, Option<HeaderMap>, Json<ProfileResponse>) {
+    let Ok(account_id) = Uuid::parse_str(&account_id) else {
+        return epic_err(make_epic_err(
+            "errors.com.epicgames.account.account_not_found",
+            &format!("Sorry, we couldn't find an account for {}", account_id),
+            std::slice::from_ref(&account_id),
+            18007,
+            StatusCode::NOT_FOUND,
+        ));
+    };
+
+    if bearer.user.account_id != account_id {
+        return epic_err(make_epic_err(
+            "errors.com.epicgames.common.authentication.authentication_failed",
+            &format!("Authentication failed for /api/game/v2/profile/{}", account_id.to_stripped_string()),
+            &[account_id.to_stripped_string()],
+            1032,
+            StatusCode::FORBIDDEN,
+        ));
+    }
+
+    let Ok(profile_id) = ProfileId::from_str(&query.profile_id) else {
+        return epic_err(make_epic_err(
+            "errors.com.epicgames.modules.profiles.operation_forbidden",
+            &format!("Unable to find template configuration for profile {}", query.profile_id),
+            std::slice::from_ref(&query.profile_id),
+            12813,
+            StatusCode::FORBIDDEN,
+        ));
+    };
+
+    // The client sends an empty body for some commands, so anything that isn't an object is
+    // treated as one with no fields.
+    let body = match serde_json::from_slice::<Value>(&body) {
+        Ok(val) if val.is_object() => val,
+        _ => Value::Object(Map::new()),
+    };
+
+    // The command is run again on a freshly loaded profile whenever something else, like a
+    // match result, saves the profile first.
+    let item_manager = state.item_manager.load_full();
+    let mut attempts = 0;
+    let (profile, base_revision, output, changes) = loop {
+        attempts += 1;
+        let mut profile = match get_or_create_profile(&state.mongo, &account_id, profile_id, &item_manager).await {
+            Ok(val) => val,
+            Err(e) => {
+                error!("Failed to get a profile: {}", e);
+                return internal_server_err();
+            }
+        };
+
+        let base_revision = profile.rvn;
+        let output = match run_command(&state, &command, &mut profile, &body).await {
+            Ok(val) => val,
+            Err(err) => return epic_err(*err),
+        };
+
+        let changes = profile.commit();
+        if changes.is_empty() {
+            break (profile, base_revision, output, changes);
+        }
+        match save_profile(&state.mongo, &profile, base_revision).await {
+            Ok(true) => break (profile, base_revision, output, changes),
+            Ok(false) if attempts < SAVE_ATTEMPTS => continue,
+            Ok(false) => {
+                error!("Gave up saving profile {} after {} conflicting writes", profile.id, attempts);
+                return internal_server_err();
+            }
+            Err(e) => {
+                error!("Failed to save a profile: {}", e);
+                return internal_server_err();
+            }
+        }
+    };
+
+    // Clients that are behind the revision the command was run against can't apply incremental
+    // changes, so they get the whole profile instead.
+    let outdated = query.rvn.is_some_and(|rvn| rvn != base_revision);
+    let profile_changes = match output {
+        CommandOutput::Changes if !outdated => changes,
+        _ => vec![profile.full_update()],
+    };
+
+    let response = ProfileResponse::Update(ProfileUpdate {
+        profile_revision: profile.rvn,
+        profile_id,
+        profile_changes_base_revision: base_revision,
+        profile_changes,
+        profile_command_revision: profile.command_revision,
+        server_time: Utc::now(),
+        response_version: 1,
+    });
+
+    (StatusCode::OK, None, Json(response))
+}
@@ -0,0 +1,114 @@
+use crate::athena::storefront;
+use crate::athena::storefront::{ShopEntry, ShopSection, DAILY_STOREFRONT};
+use crate::route::bearer::Bearer;
+use crate::{serializers, GlyphState};
+use axum::extract::State;
+use axum::Json;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+
+#[derive(Serialize)]
+pub struct Storefront {
+    name: String,
+    #[serde(rename = "catalogEntries")]
+    catalog_entries: Vec<Value>,
+}
+
+#[derive(Serialize)]
+pub struct Catalog {
+    #[serde(rename = "refreshIntervalHrs")]
+    refresh_interval_hrs: i64,
+    #[serde(rename = "dailyPurchaseHrs")]
+    daily_purchase_hrs: i64,
+    #[serde(serialize_with = "serializers::serialize_datetime")]
+    expiration: DateTime<Utc>,
+    storefronts: Vec<Storefront>,
+}
+
+/// Offer IDs only have to be stable and unique, so they are derived from the item.
+fn offer_id(template_id: &str) -> String {
+    let digest = Sha256::digest(template_id.as_bytes());
+    let hex = digest.iter().map(|byte| format!("{:02x}", byte)).collect::<String>();
+    format!("v2:/{}", hex)
+}
+
+fn catalog_entry(section: &ShopSection, entry: &ShopEntry, priority: usize) -> Value {
+    let (section_id, tile_size) = if section.storefront == DAILY_STOREFRONT {
+        ("Daily", "Small")
+    } else {
+        ("Featured", "Normal")
+    };
+
+    json!({
+        "devName": format!("[VIRTUAL]1 x {} for {} MtxCurrency", entry.template_id, entry.price),
+        "offerId": offer_id(&entry.template_id),
+        "fulfillmentIds": [],
+        "dailyLimit": -1,
+        "weeklyLimit": -1,
+        "monthlyLimit": -1,
+        "categories": [],
+        "prices": [{
+            "currencyType": "MtxCurrency",
+            "currencySubType": "",
+            "regularPrice": entry.price,
+            "dynamicRegularPrice": -1,
+            "finalPrice": entry.price,
+            "saleExpiration": "9999-12-31T23:59:59.999Z",
+            "basePrice": entry.price,
+        }],
+        "meta": {
+            "SectionId": section_id,
+            "TileSize": tile_size,
+        },
+        "matchFilter": "",
+        "filterWeight": 0,
+        "appStoreId": [],
+        "requirements": [{
+            "requirementType": "DenyOnItemOwnership",
+            "requiredId": entry.template_id,
+            "minQuantity": 1,
+        }],
+        "offerType": "StaticPrice",
+        "giftInfo": {
+            "bIsEnabled": true,
+            "forcedGiftBoxTemplateId": "",
+            "purchaseRequirements": [],
+            "giftRecordIds": [],
+        },
+        "refundable": true,
+        "metaInfo": [
+            { "key": "SectionId", "value": section_id },
+            { "key": "TileSize", "value": tile_size },
+        ],
+        "displayAssetPath": "",
+        "itemGrants": [{ "templateId": entry.template_id, "quantity": 1 }],
+        "additionalGrants": [],
+        "sortPriority": -(priority as i64),
+        "catalogGroupPriority": 0,
+    })
+}
+
+/// Serves the current rotation of the item shop, see [storefront::shop_at].
+pub async fn catalog(
+    State(state): State<Arc<GlyphState>>,
+    _bearer: Bearer,
+) -> Json<Catalog> {
+    let shop = storefront::current_shop(&state.shop_config, &state.item_manager.load());
+    let storefronts = shop.sections.iter().map(|section| Storefront {
+        name: section.storefront.to_string(),
+        catalog_entries: section.entries.iter()
+            .enumerate()
+            .map(|(priority, entry)| catalog_entry(section, entry, priority))
+            .collect(),
+    }).collect();
+
+    Json(Catalog {
+        refresh_interval_hrs: state.shop_config.daily.rotation_hours(),
+        daily_purchase_hrs: 24,
+        expiration: shop.expires_at(),
+        storefronts,
+    })
+}
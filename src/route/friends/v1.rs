@@ -0,0 +1,307 @@
+use crate::epic::epic_error::{make_epic_err, to_response, EpicErrorResponse};
+use crate::error::Error;
+use crate::friends;
+use crate::friends::{FriendRequestOutcome, Friendship, FriendsSummary, MAX_FRIENDS, MAX_PENDING_REQUESTS};
+use crate::route::bearer::Bearer;
+use crate::{serializers, GlyphState};
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+use chrono::Utc;
+use log::error;
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::Arc;
+use uuid::Uuid;
+
+#[derive(Serialize)]
+pub struct FriendEntry {
+    #[serde(rename = "accountId")]
+    account_id: String,
+    groups: Vec<String>,
+    mutual: u32,
+    alias: String,
+    note: String,
+    favorite: bool,
+    #[serde(serialize_with = "serializers::serialize_datetime")]
+    created: chrono::DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+pub struct RequestEntry {
+    #[serde(rename = "accountId")]
+    account_id: String,
+    mutual: u32,
+    favorite: bool,
+    #[serde(serialize_with = "serializers::serialize_datetime")]
+    created: chrono::DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+pub struct BlockEntry {
+    #[serde(rename = "accountId")]
+    account_id: String,
+    #[serde(serialize_with = "serializers::serialize_datetime")]
+    created: chrono::DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+pub struct LimitsReached {
+    incoming: bool,
+    outgoing: bool,
+    accepted: bool,
+}
+
+#[derive(Serialize)]
+pub struct SummaryResponse {
+    friends: Vec<FriendEntry>,
+    incoming: Vec<RequestEntry>,
+    outgoing: Vec<RequestEntry>,
+    suggested: Vec<Value>,
+    blocklist: Vec<BlockEntry>,
+    settings: Value,
+    #[serde(rename = "limitsReached")]
+    limits_reached: LimitsReached,
+}
+
+fn friend_entry(account_id: &str, friendship: &Friendship) -> FriendEntry {
+    FriendEntry {
+        account_id: friendship.other(account_id).to_string(),
+        groups: vec![],
+        mutual: 0,
+        alias: String::new(),
+        note: String::new(),
+        favorite: false,
+        created: friendship.accepted.unwrap_or(friendship.created),
+    }
+}
+
+fn request_entry(account_id: &str, friendship: &Friendship) -> RequestEntry {
+    RequestEntry {
+        account_id: friendship.other(account_id).to_string(),
+        mutual: 0,
+        favorite: false,
+        created: friendship.created,
+    }
+}
+
+fn block_entry(friendship: &Friendship) -> BlockEntry {
+    BlockEntry {
+        account_id: friendship.friend_id.clone(),
+        created: friendship.created,
+    }
+}
+
+fn internal_server_err() -> EpicErrorResponse {
+    to_response(make_epic_err(
+        "errors.com.epicgames.common.internal_server_error",
+        "Something went wrong",
+        &[],
+        -1,
+        StatusCode::INTERNAL_SERVER_ERROR,
+    ))
+}
+
+/// Turns an error from the [friends] service into the matching Epic error.
+fn friends_err(err: Error, account_id: &str, friend_id: &str) -> EpicErrorResponse {
+    let both = [account_id.to_string(), friend_id.to_string()];
+    let err = match err {
+        Error::UserNotFound => make_epic_err(
+            "errors.com.epicgames.account.account_not_found",
+            &format!("Sorry, we couldn't find an account for {}", friend_id),
+            &[friend_id.to_string()],
+            18007,
+            StatusCode::NOT_FOUND,
+        ),
+        Error::CannotFriendSelf => make_epic_err(
+            "errors.com.epicgames.friends.self_friend",
+            &format!("It's not allowed to add yourself ({}) as a friend", account_id),
+            &[account_id.to_string()],
+            14001,
+            StatusCode::BAD_REQUEST,
+        ),
+        Error::AlreadyFriends => make_epic_err(
+            "errors.com.epicgames.friends.duplicate_friendship",
+            &format!("Friendship between {} and {} already exists", account_id, friend_id),
+            &both,
+            14009,
+            StatusCode::CONFLICT,
+        ),
+        Error::FriendRequestAlreadySent => make_epic_err(
+            "errors.com.epicgames.friends.friend_request_already_sent",
+            &format!("Friendship request has already been sent to {}", friend_id),
+            &[friend_id.to_string()],
+            14014,
+            StatusCode::CONFLICT,
+        ),
+        Error::FriendshipBlocked => make_epic_err(
+            "errors.com.epicgames.friends.account_blocked",
+            &format!("Friendship between {} and {} can't be created because one of them is blocked", account_id, friend_id),
+            &both,
+            14002,
+            StatusCode::FORBIDDEN,
+        ),
+        Error::FriendshipNotFound => make_epic_err(
+            "errors.com.epicgames.friends.friendship_not_found",
+            &format!("Friendship between {} and {} does not exist", account_id, friend_id),
+            &both,
+            14004,
+            StatusCode::NOT_FOUND,
+        ),
+        Error::FriendLimitReached(limit) => make_epic_err(
+            "errors.com.epicgames.friends.friendships_limit_exceeded",
+            &format!("One of the accounts has reached the limit of {} friends", limit),
+            &[limit.to_string()],
+            14005,
+            StatusCode::CONFLICT,
+        ),
+        Error::FriendRequestLimitReached(limit) => make_epic_err(
+            "errors.com.epicgames.friends.pending_friendships_limit_exceeded",
+            &format!("One of the accounts has reached the limit of {} pending friend requests", limit),
+            &[limit.to_string()],
+            14006,
+            StatusCode::CONFLICT,
+        ),
+        _ => {
+            error!("Friends request failed: {}", err);
+            return internal_server_err();
+        }
+    };
+    to_response(err)
+}
+
+/// Parses the friend ID from the path, treating malformed IDs as accounts that don't exist.
+fn parse_friend_id(account_id: &str, friend_id: &str) -> Result<Uuid, EpicErrorResponse> {
+    Uuid::parse_str(friend_id).map_err(|_| friends_err(Error::UserNotFound, account_id, friend_id))
+}
+
+fn load_err(e: Error) -> EpicErrorResponse {
+    error!("Failed to load friends: {}", e);
+    internal_server_err()
+}
+
+pub async fn summary(
+    State(state): State<Arc<GlyphState>>,
+    bearer: Bearer,
+    Path(account_id): Path<String>,
+) -> Result<Json<SummaryResponse>, EpicErrorResponse> {
+    bearer.require_account(&account_id)?;
+    let FriendsSummary { friends, incoming, outgoing, blocklist } = friends::get_summary(&state.mongo, &bearer.user.account_id).await
+        .map_err(load_err)?;
+
+    Ok(Json(SummaryResponse {
+        limits_reached: LimitsReached {
+            incoming: incoming.len() as u64 >= MAX_PENDING_REQUESTS,
+            outgoing: outgoing.len() as u64 >= MAX_PENDING_REQUESTS,
+            accepted: friends.len() as u64 >= MAX_FRIENDS,
+        },
+        friends: friends.iter().map(|friendship| friend_entry(&account_id, friendship)).collect(),
+        incoming: incoming.iter().map(|friendship| request_entry(&account_id, friendship)).collect(),
+        outgoing: outgoing.iter().map(|friendship| request_entry(&account_id, friendship)).collect(),
+        suggested: vec![],
+        blocklist: blocklist.iter().map(block_entry).collect(),
+        settings: serde_json::json!({ "acceptInvites": "public" }),
+    }))
+}
+
+pub async fn get_friends(
+    State(state): State<Arc<GlyphState>>,
+    bearer: Bearer,
+    Path(account_id): Path<String>,
+) -> Result<Json<Vec<FriendEntry>>, EpicErrorResponse> {
+    bearer.require_account(&account_id)?;
+    let friends = friends::get_friends(&state.mongo, &bearer.user.account_id).await.map_err(load_err)?;
+    Ok(Json(friends.iter().map(|friendship| friend_entry(&account_id, friendship)).collect()))
+}
+
+pub async fn get_incoming(
+    State(state): State<Arc<GlyphState>>,
+    bearer: Bearer,
+    Path(account_id): Path<String>,
+) -> Result<Json<Vec<RequestEntry>>, EpicErrorResponse> {
+    bearer.require_account(&account_id)?;
+    let incoming = friends::get_incoming(&state.mongo, &bearer.user.account_id).await.map_err(load_err)?;
+    Ok(Json(incoming.iter().map(|friendship| request_entry(&account_id, friendship)).collect()))
+}
+
+pub async fn get_outgoing(
+    State(state): State<Arc<GlyphState>>,
+    bearer: Bearer,
+    Path(account_id): Path<String>,
+) -> Result<Json<Vec<RequestEntry>>, EpicErrorResponse> {
+    bearer.require_account(&account_id)?;
+    let outgoing = friends::get_outgoing(&state.mongo, &bearer.user.account_id).await.map_err(load_err)?;
+    Ok(Json(outgoing.iter().map(|friendship| request_entry(&account_id, friendship)).collect()))
+}
+
+pub async fn get_blocklist(
+    State(state): State<Arc<GlyphState>>,
+    bearer: Bearer,
+    Path(account_id): Path<String>,
+) -> Result<Json<Vec<BlockEntry>>, EpicErrorResponse> {
+    bearer.require_account(&account_id)?;
+    let blocklist = friends::get_blocklist(&state.mongo, &bearer.user.account_id).await.map_err(load_err)?;
+    Ok(Json(blocklist.iter().map(block_entry).collect()))
+}
+
+/// Sends a friend request, or accepts the one the other account already sent.
+pub async fn add_friend(
+    State(state): State<Arc<GlyphState>>,
+    bearer: Bearer,
+    Path((account_id, friend_id)): Path<(String, String)>,
+) -> Result<StatusCode, EpicErrorResponse> {
+    bearer.require_account(&account_id)?;
+    let friend_uuid = parse_friend_id(&account_id, &friend_id)?;
+    let (FriendRequestOutcome::Sent(friendship) | FriendRequestOutcome::Accepted(friendship)) =
+        friends::send_friend_request(&state.mongo, &bearer.user.account_id, &friend_uuid).await
+            .map_err(|e| friends_err(e, &account_id, &friend_id))?;
+    friends::notify_friendship(&state.xmpp, &friendship);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Removes a friend or declines or cancels a pending request.
+pub async fn remove_friend(
+    State(state): State<Arc<GlyphState>>,
+    bearer: Bearer,
+    Path((account_id, friend_id)): Path<(String, String)>,
+) -> Result<StatusCode, EpicErrorResponse> {
+    bearer.require_account(&account_id)?;
+    let friend_uuid = parse_friend_id(&account_id, &friend_id)?;
+    let removed = friends::remove_friend(&state.mongo, &bearer.user.account_id, &friend_uuid).await
+        .map_err(|e| friends_err(e, &account_id, &friend_id))?;
+    friends::notify_removal(&state.xmpp, &removed);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn block(
+    State(state): State<Arc<GlyphState>>,
+    bearer: Bearer,
+    Path((account_id, friend_id)): Path<(String, String)>,
+) -> Result<StatusCode, EpicErrorResponse> {
+    bearer.require_account(&account_id)?;
+    let friend_uuid = parse_friend_id(&account_id, &friend_id)?;
+    let removed = friends::block(&state.mongo, &bearer.user.account_id, &friend_uuid).await
+        .map_err(|e| friends_err(e, &account_id, &friend_id))?;
+    if let Some(removed) = removed {
+        friends::notify_removal(&state.xmpp, &removed);
+    }
+    friends::notify_block(&state.xmpp, &bearer.user.account_id, &friend_uuid, true);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn unblock(
+    State(state): State<Arc<GlyphState>>,
+    bearer: Bearer,
+    Path((account_id, friend_id)): Path<(String, String)>,
+) -> Result<StatusCode, EpicErrorResponse> {
+    bearer.require_account(&account_id)?;
+    let friend_uuid = parse_friend_id(&account_id, &friend_id)?;
+    match friends::unblock(&state.mongo, &bearer.user.account_id, &friend_uuid).await {
+        Ok(true) => {
+            friends::notify_block(&state.xmpp, &bearer.user.account_id, &friend_uuid, false);
+            Ok(StatusCode::NO_CONTENT)
+        }
+        Ok(false) => Err(friends_err(Error::FriendshipNotFound, &account_id, &friend_id)),
+        Err(e) => Err(friends_err(e, &account_id, &friend_id)),
+    }
+}
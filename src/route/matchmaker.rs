@@ -0,0 +1,38 @@
+use crate::epic::epic_error::{make_epic_err, to_response};
+use crate::matchmaking::matchmaker::MatchmakingTicket;
+use crate::{matchmaking, GlyphState};
+use axum::extract::{State, WebSocketUpgrade};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use std::sync::Arc;
+
+/// Reads the ticket from an `Authorization: Epic-Signed mms-player <payload> <signature>` header
+/// and verifies it.
+fn ticket(state: &GlyphState, headers: &HeaderMap) -> Option<MatchmakingTicket> {
+    let header = headers.get("Authorization")?.to_str().ok()?;
+    let mut parts = header.split_whitespace();
+    if parts.next()? != "Epic-Signed" || parts.next()? != "mms-player" {
+        return None;
+    }
+    let (payload, signature) = (parts.next()?, parts.next()?);
+    let claims = state.auth_manager.verify_matchmaking_ticket(payload, signature).ok()?;
+    MatchmakingTicket::from_claims(&claims)
+}
+
+/// Upgrades to a matchmaker connection, see [matchmaking::matchmaker::run].
+pub async fn connect(
+    State(state): State<Arc<GlyphState>>,
+    headers: HeaderMap,
+    ws: WebSocketUpgrade,
+) -> Response {
+    let Some(ticket) = ticket(&state, &headers) else {
+        return to_response(make_epic_err(
+            "errors.com.epicgames.matchmaking.invalid_ticket",
+            "The matchmaking ticket is missing, invalid or has expired",
+            &[],
+            -1,
+            StatusCode::UNAUTHORIZED,
+        )).into_response();
+    };
+    ws.on_upgrade(move |socket| matchmaking::matchmaker::run(state, socket, ticket))
+}
@@ -0,0 +1,311 @@
+use crate::epic::epic_error::{make_epic_err, to_response, EpicErrorResponse};
+use crate::error::Error;
+use crate::party;
+use crate::party::{CreateParty, JoinInfo, MetaPatch, Party, PartyInvite, PartyPatch, PartyPing};
+use crate::route::bearer::Bearer;
+use crate::util::UuidString;
+use crate::GlyphState;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::Json;
+use log::error;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::sync::Arc;
+
+#[derive(Serialize)]
+pub struct UserParties {
+    current: Vec<Party>,
+    pending: Vec<Value>,
+    invites: Vec<PartyInvite>,
+    pings: Vec<PartyPing>,
+}
+
+#[derive(Serialize)]
+pub struct JoinResponse {
+    status: String,
+    party_id: String,
+}
+
+#[derive(Deserialize)]
+pub struct InviteQuery {
+    #[serde(rename = "sendPing", default)]
+    send_ping: bool,
+}
+
+/// Turns an error from the [party] service into the matching Epic error.
+fn party_err(err: Error) -> EpicErrorResponse {
+    let err = match err {
+        Error::PartyNotFound(party_id) => make_epic_err(
+            "errors.com.epicgames.social.party.party_not_found",
+            &format!("Sorry, we couldn't find a party by id {}", party_id),
+            &[party_id],
+            51002,
+            StatusCode::NOT_FOUND,
+        ),
+        Error::PartyMemberNotFound(account_id) => make_epic_err(
+            "errors.com.epicgames.social.party.member_not_found",
+            &format!("Sorry, we couldn't find a party member by id {}", account_id),
+            &[account_id],
+            51004,
+            StatusCode::NOT_FOUND,
+        ),
+        Error::NotPartyCaptain => make_epic_err(
+            "errors.com.epicgames.social.party.party_change_forbidden",
+            "Only the party captain can make that change",
+            &[],
+            51015,
+            StatusCode::FORBIDDEN,
+        ),
+        Error::PartyFull => make_epic_err(
+            "errors.com.epicgames.social.party.party_is_full",
+            "The party is full",
+            &[],
+            51009,
+            StatusCode::CONFLICT,
+        ),
+        Error::PartyJoinForbidden => make_epic_err(
+            "errors.com.epicgames.social.party.party_join_forbidden",
+            "The party can only be joined with an invite",
+            &[],
+            51006,
+            StatusCode::FORBIDDEN,
+        ),
+        Error::PartyInviteNotFound => make_epic_err(
+            "errors.com.epicgames.social.party.invite_not_found",
+            "Sorry, we couldn't find that invite",
+            &[],
+            51019,
+            StatusCode::NOT_FOUND,
+        ),
+        Error::PartyPingNotFound => make_epic_err(
+            "errors.com.epicgames.social.party.ping_not_found",
+            "Sorry, we couldn't find that ping",
+            &[],
+            51021,
+            StatusCode::NOT_FOUND,
+        ),
+        Error::StalePartyRevision(current, supplied) => make_epic_err(
+            "errors.com.epicgames.social.party.stale_revision",
+            &format!("The revision {} is older than the current revision {}", supplied, current),
+            &[current.to_string(), supplied.to_string()],
+            51010,
+            StatusCode::CONFLICT,
+        ),
+        _ => {
+            error!("Party request failed: {}", err);
+            make_epic_err(
+                "errors.com.epicgames.common.internal_server_error",
+                "Something went wrong",
+                &[],
+                -1,
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        }
+    };
+    to_response(err)
+}
+
+/// Moves the account out of the supplied party.
+async fn leave_party(state: &GlyphState, party_id: &str, account_id: &str) -> Result<(), EpicErrorResponse> {
+    let departure = state.parties.remove_member(&state.mongo, party_id, account_id).await.map_err(party_err)?;
+    party::notify_member_left(&state.xmpp, party_id, &departure, false);
+    Ok(())
+}
+
+async fn join_party(state: &GlyphState, party_id: &str, account_id: &str, join_info: JoinInfo) -> Result<Json<JoinResponse>, EpicErrorResponse> {
+    // The account only leaves its current party once the join has succeeded, so a join that
+    // fails leaves it where it was.
+    let previous = state.parties.party_of(account_id).filter(|current| current.id != party_id);
+    let party = state.parties.join(&state.mongo, party_id, account_id, join_info).await.map_err(party_err)?;
+    if let Some(previous) = previous {
+        leave_party(state, &previous.id, account_id).await?;
+    }
+    party::notify_member_joined(&state.xmpp, &party, account_id);
+    Ok(Json(JoinResponse {
+        status: "JOINED".to_string(),
+        party_id: party.id,
+    }))
+}
+
+pub async fn get_user(
+    State(state): State<Arc<GlyphState>>,
+    bearer: Bearer,
+    Path(account_id): Path<String>,
+) -> Result<Json<UserParties>, EpicErrorResponse> {
+    bearer.require_account(&account_id)?;
+    Ok(Json(UserParties {
+        current: state.parties.party_of(&account_id).into_iter().collect(),
+        pending: vec![],
+        invites: state.parties.invites_for(&account_id),
+        pings: state.parties.pings_for(&account_id),
+    }))
+}
+
+/// Creates a party with the caller as captain, leaving the party they were in before.
+pub async fn create_party(
+    State(state): State<Arc<GlyphState>>,
+    bearer: Bearer,
+    Json(body): Json<CreateParty>,
+) -> Result<Json<Party>, EpicErrorResponse> {
+    let account_id = bearer.user.account_id.to_stripped_string();
+    if let Some(current) = state.parties.party_of(&account_id) {
+        leave_party(&state, &current.id, &account_id).await?;
+    }
+    let party = state.parties.create(&state.mongo, &account_id, body).await.map_err(party_err)?;
+    Ok(Json(party))
+}
+
+pub async fn get_party(
+    State(state): State<Arc<GlyphState>>,
+    _bearer: Bearer,
+    Path(party_id): Path<String>,
+) -> Result<Json<Party>, EpicErrorResponse> {
+    Ok(Json(state.parties.get(&party_id).map_err(party_err)?))
+}
+
+pub async fn update_party(
+    State(state): State<Arc<GlyphState>>,
+    bearer: Bearer,
+    Path(party_id): Path<String>,
+    Json(patch): Json<PartyPatch>,
+) -> Result<StatusCode, EpicErrorResponse> {
+    let account_id = bearer.user.account_id.to_stripped_string();
+    let party = state.parties.update_party(&state.mongo, &party_id, &account_id, &patch).await.map_err(party_err)?;
+    party::notify_party_updated(&state.xmpp, &party, &patch.meta);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn update_member_meta(
+    State(state): State<Arc<GlyphState>>,
+    bearer: Bearer,
+    Path((party_id, account_id)): Path<(String, String)>,
+    Json(patch): Json<MetaPatch>,
+) -> Result<StatusCode, EpicErrorResponse> {
+    bearer.require_account(&account_id)?;
+    let party = state.parties.update_member_meta(&state.mongo, &party_id, &account_id, &patch).await.map_err(party_err)?;
+    party::notify_member_state_updated(&state.xmpp, &party, &account_id, &patch);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn join(
+    State(state): State<Arc<GlyphState>>,
+    bearer: Bearer,
+    Path((party_id, account_id)): Path<(String, String)>,
+    Json(join_info): Json<JoinInfo>,
+) -> Result<Json<JoinResponse>, EpicErrorResponse> {
+    bearer.require_account(&account_id)?;
+    join_party(&state, &party_id, &account_id, join_info).await
+}
+
+/// Leaves the party when the caller removes themselves, otherwise kicks the member, which only
+/// the captain can do.
+pub async fn remove_member(
+    State(state): State<Arc<GlyphState>>,
+    bearer: Bearer,
+    Path((party_id, account_id)): Path<(String, String)>,
+) -> Result<StatusCode, EpicErrorResponse> {
+    let caller_id = bearer.user.account_id.to_stripped_string();
+    let kicked = caller_id != account_id;
+    let departure = if kicked {
+        state.parties.kick(&state.mongo, &party_id, &caller_id, &account_id).await
+    } else {
+        state.parties.remove_member(&state.mongo, &party_id, &account_id).await
+    }.map_err(party_err)?;
+    party::notify_member_left(&state.xmpp, &party_id, &departure, kicked);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn promote(
+    State(state): State<Arc<GlyphState>>,
+    bearer: Bearer,
+    Path((party_id, account_id)): Path<(String, String)>,
+) -> Result<StatusCode, EpicErrorResponse> {
+    let caller_id = bearer.user.account_id.to_stripped_string();
+    let party = state.parties.promote(&state.mongo, &party_id, &caller_id, &account_id).await.map_err(party_err)?;
+    party::notify_new_captain(&state.xmpp, &party);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn invite(
+    State(state): State<Arc<GlyphState>>,
+    bearer: Bearer,
+    Path((party_id, account_id)): Path<(String, String)>,
+    Query(query): Query<InviteQuery>,
+    meta: Option<Json<Map<String, Value>>>,
+) -> Result<StatusCode, EpicErrorResponse> {
+    let caller_id = bearer.user.account_id.to_stripped_string();
+    let meta = meta.map(|Json(meta)| meta).unwrap_or_default();
+    let (party, invite) = state.parties.invite(&state.mongo, &party_id, &caller_id, &account_id, meta).await
+        .map_err(party_err)?;
+    party::notify_invite(&state.xmpp, &party, &invite);
+    if query.send_ping {
+        let ping = state.parties.ping(&caller_id, &account_id, Map::new());
+        party::notify_ping(&state.xmpp, &ping, &bearer.user.display_name);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn decline_invite(
+    State(state): State<Arc<GlyphState>>,
+    bearer: Bearer,
+    Path((party_id, account_id)): Path<(String, String)>,
+) -> Result<StatusCode, EpicErrorResponse> {
+    bearer.require_account(&account_id)?;
+    let (party, invite) = state.parties.decline_invite(&state.mongo, &party_id, &account_id).await.map_err(party_err)?;
+    party::notify_invite_declined(&state.xmpp, &party, &invite);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Pings `account_id` on behalf of the caller, asking them to join the caller's party.
+pub async fn create_ping(
+    State(state): State<Arc<GlyphState>>,
+    bearer: Bearer,
+    Path((account_id, pinger_id)): Path<(String, String)>,
+    meta: Option<Json<Map<String, Value>>>,
+) -> Result<Json<PartyPing>, EpicErrorResponse> {
+    bearer.require_account(&pinger_id)?;
+    let meta = meta.map(|Json(meta)| meta).unwrap_or_default();
+    let ping = state.parties.ping(&pinger_id, &account_id, meta);
+    party::notify_ping(&state.xmpp, &ping, &bearer.user.display_name);
+    Ok(Json(ping))
+}
+
+/// Dismisses a ping. Either the pinger or the pinged account can do this.
+pub async fn delete_ping(
+    State(state): State<Arc<GlyphState>>,
+    bearer: Bearer,
+    Path((account_id, pinger_id)): Path<(String, String)>,
+) -> Result<StatusCode, EpicErrorResponse> {
+    if bearer.require_account(&account_id).is_err() {
+        bearer.require_account(&pinger_id)?;
+    }
+    if !state.parties.delete_ping(&pinger_id, &account_id) {
+        return Err(party_err(Error::PartyPingNotFound));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn ping_parties(
+    State(state): State<Arc<GlyphState>>,
+    bearer: Bearer,
+    Path((account_id, pinger_id)): Path<(String, String)>,
+) -> Result<Json<Vec<Party>>, EpicErrorResponse> {
+    bearer.require_account(&account_id)?;
+    Ok(Json(state.parties.pinger_parties(&account_id, &pinger_id).map_err(party_err)?))
+}
+
+/// Joins the party of a player that pinged the caller.
+pub async fn join_from_ping(
+    State(state): State<Arc<GlyphState>>,
+    bearer: Bearer,
+    Path((account_id, pinger_id)): Path<(String, String)>,
+    Json(join_info): Json<JoinInfo>,
+) -> Result<Json<JoinResponse>, EpicErrorResponse> {
+    bearer.require_account(&account_id)?;
+    let party = state.parties.pinger_parties(&account_id, &pinger_id).map_err(party_err)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| party_err(Error::PartyMemberNotFound(pinger_id.clone())))?;
+    join_party(&state, &party.id, &account_id, join_info).await
+}
@@ -1,10 +1,10 @@
-use crate::route::account;
+use crate::route::{account, fortnite, friends, matchmaker, party, servers, stats, xmpp};
 use crate::GlyphState;
 use axum::extract::Request;
 use axum::http::header::DATE;
 use axum::middleware::Next;
 use axum::response::Response;
-use axum::{middleware, routing::{post, Router}};
+use axum::{middleware, routing::{delete, get, patch, post, put, Router}};
 use chrono::Utc;
 use std::sync::Arc;
 
@@ -21,6 +21,50 @@ async fn add_headers(
 pub fn create_router(shared_state: Arc<GlyphState>) -> Router {
     Router::new()
         .route("/account/api/oauth/token", post(account::auth::oauth))
+        .route("/account/api/oauth/verify", get(account::auth::verify))
+        .route("/account/api/oauth/sessions/kill", delete(account::auth::kill_sessions))
+        .route("/account/api/oauth/sessions/kill/:token", delete(account::auth::kill_session))
+        .route("/account/api/public/account", get(account::public::get_accounts))
+        .route("/account/api/public/account/:accountId", get(account::public::get_account))
+        .route("/account/api/public/account/:accountId/displayName", put(account::public::update_display_name))
+        .route("/account/api/public/account/:accountId/externalAuths", get(account::public::get_external_auths))
+        .route("/account/api/public/account/displayName/:displayName", get(account::public::get_account_by_display_name))
+        .route("/friends/api/v1/:accountId/summary", get(friends::v1::summary))
+        .route("/friends/api/v1/:accountId/friends", get(friends::v1::get_friends))
+        .route("/friends/api/v1/:accountId/friends/:friendId", post(friends::v1::add_friend).delete(friends::v1::remove_friend))
+        .route("/friends/api/v1/:accountId/incoming", get(friends::v1::get_incoming))
+        .route("/friends/api/v1/:accountId/outgoing", get(friends::v1::get_outgoing))
+        .route("/friends/api/v1/:accountId/blocklist", get(friends::v1::get_blocklist))
+        .route("/friends/api/v1/:accountId/blocklist/:friendId", post(friends::v1::block).delete(friends::v1::unblock))
+        .route("/party/api/v1/Fortnite/user/:accountId", get(party::v1::get_user))
+        .route("/party/api/v1/Fortnite/user/:accountId/pings/:pingerId", post(party::v1::create_ping).delete(party::v1::delete_ping))
+        .route("/party/api/v1/Fortnite/user/:accountId/pings/:pingerId/parties", get(party::v1::ping_parties))
+        .route("/party/api/v1/Fortnite/user/:accountId/pings/:pingerId/join", post(party::v1::join_from_ping))
+        .route("/party/api/v1/Fortnite/parties", post(party::v1::create_party))
+        .route("/party/api/v1/Fortnite/parties/:partyId", get(party::v1::get_party).patch(party::v1::update_party))
+        .route("/party/api/v1/Fortnite/parties/:partyId/members/:accountId", delete(party::v1::remove_member))
+        .route("/party/api/v1/Fortnite/parties/:partyId/members/:accountId/meta", patch(party::v1::update_member_meta))
+        .route("/party/api/v1/Fortnite/parties/:partyId/members/:accountId/join", post(party::v1::join))
+        .route("/party/api/v1/Fortnite/parties/:partyId/members/:accountId/promote", post(party::v1::promote))
+        .route("/party/api/v1/Fortnite/parties/:partyId/invites/:accountId", post(party::v1::invite))
+        .route("/party/api/v1/Fortnite/parties/:partyId/invites/:accountId/decline", post(party::v1::decline_invite))
+        .route("/fortnite/api/storefront/v2/catalog", get(fortnite::storefront::catalog))
+        .route("/fortnite/api/game/v2/profile/:accountId/client/:command", post(fortnite::profile::client_command))
+        .route("/fortnite/api/game/v2/matchmakingservice/ticket/player/:accountId", get(fortnite::matchmaking::ticket))
+        .route("/fortnite/api/game/v2/matchmaking/account/:accountId/session/:sessionId", get(fortnite::matchmaking::session_key))
+        .route("/fortnite/api/matchmaking/session/findPlayer/:accountId", get(fortnite::matchmaking::find_player))
+        .route("/fortnite/api/matchmaking/session/:sessionId", get(fortnite::matchmaking::get_session))
+        .route("/fortnite/api/matchmaking/session/:sessionId/join", post(fortnite::matchmaking::join_session))
+        .route("/fortnite/api/statsv2/account/:accountId", get(stats::v2::get_stats))
+        .route("/statsproxy/api/statsv2/account/:accountId", get(stats::v2::get_stats))
+        .route("/statsproxy/api/statsv2/query", post(stats::v2::query_stats))
+        .route("/statsproxy/api/statsv2/leaderboards/:playlist", get(stats::v2::get_leaderboard))
+        .route("/matchmaking", get(matchmaker::connect))
+        .route("/glyph/api/v1/servers", post(servers::register))
+        .route("/glyph/api/v1/servers/:serverId", delete(servers::deregister))
+        .route("/glyph/api/v1/servers/:serverId/heartbeat", post(servers::heartbeat))
+        .route("/glyph/api/v1/servers/:serverId/results", post(servers::report_results))
+        .route("/", get(xmpp::connect))
         .layer(middleware::from_fn(add_headers))
         .with_state(shared_state)
 }
@@ -0,0 +1,168 @@
+use crate::athena::matches;
+use crate::athena::matches::PlayerResult;
+use crate::epic::epic_error::{make_epic_err, to_response, EpicErrorResponse};
+use crate::matchmaking::registry::GameServer;
+use crate::route::bearer::GameServerAuth;
+use crate::GlyphState;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Arc;
+
+#[derive(Deserialize)]
+pub struct RegisterServer {
+    ip: String,
+    port: u16,
+    region: String,
+    playlist: String,
+    #[serde(rename = "maxPlayers")]
+    max_players: u32,
+}
+
+#[derive(Serialize)]
+pub struct RegisteredServer {
+    id: String,
+    #[serde(rename = "sessionId")]
+    session_id: String,
+    #[serde(rename = "sessionKey")]
+    session_key: String,
+}
+
+#[derive(Deserialize)]
+pub struct Heartbeat {
+    /// How many players are connected, if the server is reporting it.
+    players: Option<u32>,
+}
+
+#[derive(Deserialize)]
+pub struct MatchResults {
+    players: Vec<PlayerResult>,
+}
+
+#[derive(Serialize)]
+pub struct RecordedResults {
+    #[serde(rename = "endedSessionId")]
+    ended_session_id: String,
+    /// The session the server hosts next, players present its key to join it.
+    #[serde(rename = "sessionId")]
+    session_id: String,
+    #[serde(rename = "sessionKey")]
+    session_key: String,
+    /// The account IDs whose results were recorded.
+    recorded: Vec<String>,
+    /// The account IDs whose results were ignored, because they weren't assigned to the session,
+    /// were reported more than once, had no placement or couldn't be stored.
+    skipped: Vec<String>,
+}
+
+fn server_not_found(server_id: &str) -> EpicErrorResponse {
+    to_response(make_epic_err(
+        "errors.com.glyph.servers.server_not_found",
+        &format!("Sorry, we couldn't find a server by id {}. It may have been evicted, register it again.", server_id),
+        &[server_id.to_string()],
+        -1,
+        StatusCode::NOT_FOUND,
+    ))
+}
+
+/// Fails unless the server is registered and the client is allowed to manage it, see
+/// [crate::matchmaking::registry::ServerRegistry::managed_by].
+fn require_manager(state: &GlyphState, auth: &GameServerAuth, server_id: &str) -> Result<(), EpicErrorResponse> {
+    match state.servers.managed_by(server_id, &auth.client_id) {
+        Some(true) => Ok(()),
+        Some(false) => Err(to_response(make_epic_err(
+            "errors.com.epicgames.common.missing_permission",
+            &format!("Sorry the client '{}' is not allowed to manage game server {}", auth.client_id, server_id),
+            &[auth.client_id.clone(), server_id.to_string()],
+            1023,
+            StatusCode::FORBIDDEN,
+        ))),
+        None => Err(server_not_found(server_id)),
+    }
+}
+
+/// Adds a server to the registry so players can be matched into it. The server has to send a
+/// heartbeat at least once a minute to stay registered.
+pub async fn register(
+    State(state): State<Arc<GlyphState>>,
+    auth: GameServerAuth,
+    Json(body): Json<RegisterServer>,
+) -> Json<RegisteredServer> {
+    let server = GameServer::registered(&auth.client_id, &body.ip, body.port, &body.region, &body.playlist, body.max_players);
+    info!("Client {} registered game server {} at {}:{} for {} in {}", auth.client_id, server.id, server.ip, server.port, server.playlist, server.region);
+    let response = RegisteredServer {
+        id: server.id.clone(),
+        session_id: server.session_id.clone(),
+        session_key: server.session_key.clone(),
+    };
+    state.servers.register(server);
+    Json(response)
+}
+
+pub async fn heartbeat(
+    State(state): State<Arc<GlyphState>>,
+    auth: GameServerAuth,
+    Path(server_id): Path<String>,
+    Json(body): Json<Heartbeat>,
+) -> Result<StatusCode, EpicErrorResponse> {
+    require_manager(&state, &auth, &server_id)?;
+    if !state.servers.heartbeat(&server_id, body.players) {
+        return Err(server_not_found(&server_id));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn deregister(
+    State(state): State<Arc<GlyphState>>,
+    auth: GameServerAuth,
+    Path(server_id): Path<String>,
+) -> Result<StatusCode, EpicErrorResponse> {
+    require_manager(&state, &auth, &server_id)?;
+    if !state.servers.deregister(&server_id) {
+        return Err(server_not_found(&server_id));
+    }
+    info!("Game server {} deregistered", server_id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Records the results of the match the server just hosted and starts a new session on it.
+/// Results are only recorded for players the matchmaker assigned to the session, once each.
+pub async fn report_results(
+    State(state): State<Arc<GlyphState>>,
+    auth: GameServerAuth,
+    Path(server_id): Path<String>,
+    Json(body): Json<MatchResults>,
+) -> Result<Json<RecordedResults>, EpicErrorResponse> {
+    require_manager(&state, &auth, &server_id)?;
+    let (server, next) = state.servers.end_session(&server_id).ok_or_else(|| server_not_found(&server_id))?;
+    let item_manager = state.item_manager.load();
+
+    let mut unrecorded = server.players.iter().collect::<HashSet<_>>();
+    let mut recorded = vec![];
+    let mut skipped = vec![];
+    for result in &body.players {
+        if result.placement == 0 || !unrecorded.remove(&result.account_id) {
+            skipped.push(result.account_id.clone());
+            continue;
+        }
+        match matches::record_result(&state.mongo, &item_manager, &server.id, &server.session_id, result).await {
+            Ok(_) => recorded.push(result.account_id.clone()),
+            Err(e) => {
+                warn!("Failed to record the match result of {}: {}", result.account_id, e);
+                skipped.push(result.account_id.clone());
+            }
+        }
+    }
+
+    info!("Session {} on game server {} ended, recorded {} results", server.session_id, server.id, recorded.len());
+    Ok(Json(RecordedResults {
+        ended_session_id: server.session_id,
+        session_id: next.session_id,
+        session_key: next.session_key,
+        recorded,
+        skipped,
+    }))
+}
@@ -0,0 +1,196 @@
+use crate::epic::epic_error::{make_epic_err, to_response, EpicErrorResponse};
+use crate::error::Error;
+use crate::route::bearer::Bearer;
+use crate::stats;
+use crate::stats::{LeaderboardStat, PlaylistStats};
+use crate::user;
+use crate::util::UuidString;
+use crate::GlyphState;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::Json;
+use log::error;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// The most owners a bulk query can ask for.
+const MAX_BULK_OWNERS: usize = 100;
+const DEFAULT_PAGE_SIZE: u64 = 50;
+const MAX_PAGE_SIZE: u64 = 100;
+
+#[derive(Deserialize)]
+pub struct StatsQuery {
+    #[serde(rename = "startTime")]
+    start_time: Option<i64>,
+    #[serde(rename = "endTime")]
+    end_time: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct BulkStatsQuery {
+    owners: Vec<String>,
+    /// The stat names to return, or every stat if empty.
+    #[serde(default)]
+    stats: Vec<String>,
+    #[serde(rename = "startDate")]
+    start_date: Option<i64>,
+    #[serde(rename = "endDate")]
+    end_date: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct LeaderboardQuery {
+    stat: Option<LeaderboardStat>,
+    season: Option<u32>,
+    page: Option<u64>,
+    #[serde(rename = "pageSize")]
+    page_size: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct StatsResponse {
+    #[serde(rename = "startTime")]
+    start_time: i64,
+    #[serde(rename = "endTime")]
+    end_time: i64,
+    stats: Map<String, Value>,
+    #[serde(rename = "accountId")]
+    account_id: String,
+}
+
+#[derive(Serialize)]
+pub struct LeaderboardEntry {
+    #[serde(rename = "accountId")]
+    account_id: String,
+    #[serde(rename = "displayName")]
+    display_name: Option<String>,
+    value: i64,
+    rank: u64,
+}
+
+#[derive(Serialize)]
+pub struct LeaderboardResponse {
+    playlist: String,
+    season: u32,
+    stat: String,
+    page: u64,
+    #[serde(rename = "pageSize")]
+    page_size: u64,
+    #[serde(rename = "totalPages")]
+    total_pages: u64,
+    #[serde(rename = "totalEntries")]
+    total_entries: u64,
+    entries: Vec<LeaderboardEntry>,
+}
+
+fn load_err(e: Error) -> EpicErrorResponse {
+    error!("Failed to load stats: {}", e);
+    to_response(make_epic_err(
+        "errors.com.epicgames.common.internal_server_error",
+        "Something went wrong",
+        &[],
+        -1,
+        StatusCode::INTERNAL_SERVER_ERROR,
+    ))
+}
+
+/// Stats aren't split by time, only by season, so the window is echoed back as is and every
+/// season is included.
+fn stats_response(account_id: &str, stats: &[PlaylistStats], start_time: Option<i64>, end_time: Option<i64>) -> StatsResponse {
+    StatsResponse {
+        start_time: start_time.unwrap_or(0),
+        end_time: end_time.unwrap_or(i64::MAX),
+        stats: stats::to_statsv2(stats),
+        account_id: account_id.to_string(),
+    }
+}
+
+pub async fn get_stats(
+    State(state): State<Arc<GlyphState>>,
+    _bearer: Bearer,
+    Path(account_id): Path<String>,
+    Query(query): Query<StatsQuery>,
+) -> Result<Json<StatsResponse>, EpicErrorResponse> {
+    let stats = stats::get_stats(&state.mongo, &account_id, None).await.map_err(load_err)?;
+    Ok(Json(stats_response(&account_id, &stats, query.start_time, query.end_time)))
+}
+
+pub async fn query_stats(
+    State(state): State<Arc<GlyphState>>,
+    _bearer: Bearer,
+    Json(query): Json<BulkStatsQuery>,
+) -> Result<Json<Vec<StatsResponse>>, EpicErrorResponse> {
+    if query.owners.len() > MAX_BULK_OWNERS {
+        return Err(to_response(make_epic_err(
+            "errors.com.epicgames.statsproxy.too_many_owners",
+            &format!("Sorry, you can't request stats for more than {} accounts at once", MAX_BULK_OWNERS),
+            &[MAX_BULK_OWNERS.to_string()],
+            -1,
+            StatusCode::BAD_REQUEST,
+        )));
+    }
+
+    let stats = stats::get_stats_bulk(&state.mongo, &query.owners).await.map_err(load_err)?;
+    let mut responses = vec![];
+    for owner in &query.owners {
+        let stats = stats.get(owner).map(Vec::as_slice).unwrap_or_default();
+        let mut response = stats_response(owner, stats, query.start_date, query.end_date);
+        if !query.stats.is_empty() {
+            response.stats.retain(|name, _| query.stats.contains(name));
+        }
+        responses.push(response);
+    }
+    Ok(Json(responses))
+}
+
+/// Ranks everyone that played the playlist in a season, the current one by default.
+pub async fn get_leaderboard(
+    State(state): State<Arc<GlyphState>>,
+    _bearer: Bearer,
+    Path(playlist): Path<String>,
+    Query(query): Query<LeaderboardQuery>,
+) -> Result<Json<LeaderboardResponse>, EpicErrorResponse> {
+    let stat = query.stat.unwrap_or(LeaderboardStat::Wins);
+    let season = query.season.unwrap_or_else(stats::current_season);
+    let page = query.page.unwrap_or(0);
+    let page_size = query.page_size.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    // Mongo stores the skip as an i64, so offsets past that are as invalid as overflowing ones.
+    let Some(offset) = page.checked_mul(page_size).filter(|offset| i64::try_from(*offset).is_ok()) else {
+        return Err(to_response(make_epic_err(
+            "errors.com.epicgames.statsproxy.invalid_page",
+            &format!("Page {} is out of range", page),
+            &[page.to_string()],
+            -1,
+            StatusCode::BAD_REQUEST,
+        )));
+    };
+
+    let leaderboard = stats::get_leaderboard(&state.mongo, season, &playlist, stat, offset, page_size).await
+        .map_err(load_err)?;
+    let account_ids = leaderboard.entries.iter()
+        .filter_map(|entry| Uuid::parse_str(&entry.account_id).ok())
+        .collect::<Vec<_>>();
+    let users = user::get_users(&state.mongo, &account_ids).await.map_err(load_err)?;
+
+    let entries = leaderboard.entries.iter().enumerate().map(|(index, entry)| LeaderboardEntry {
+        account_id: entry.account_id.clone(),
+        display_name: users.iter()
+            .find(|user| user.account_id.to_stripped_string() == entry.account_id)
+            .map(|user| user.display_name.clone()),
+        value: stat.value(entry),
+        rank: offset + index as u64 + 1,
+    }).collect();
+
+    Ok(Json(LeaderboardResponse {
+        playlist: playlist.to_lowercase(),
+        season,
+        stat: stat.name().to_string(),
+        page,
+        page_size,
+        total_pages: leaderboard.total_entries.div_ceil(page_size),
+        total_entries: leaderboard.total_entries,
+        entries,
+    }))
+}
@@ -0,0 +1,13 @@
+use crate::{xmpp, GlyphState};
+use axum::extract::{State, WebSocketUpgrade};
+use axum::response::Response;
+use std::sync::Arc;
+
+/// Upgrades to an XMPP-over-WebSocket connection, see [xmpp::session::run].
+pub async fn connect(
+    State(state): State<Arc<GlyphState>>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    ws.protocols(["xmpp"])
+        .on_upgrade(move |socket| xmpp::session::run(state, socket))
+}
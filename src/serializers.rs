@@ -1,3 +1,5 @@
+use serde::Deserialize;
+
 pub fn serialize_datetime<S>(
     date: &chrono::DateTime<chrono::Utc>,
     serializer: S,
@@ -6,4 +8,15 @@ pub fn serialize_datetime<S>(
 {
     let timestamp = date.to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
     serializer.serialize_str(&timestamp)
-}
\ No newline at end of file
+}
+
+pub fn deserialize_datetime<'de, D>(
+    deserializer: D,
+) -> Result<chrono::DateTime<chrono::Utc>, D::Error> where
+    D: serde::Deserializer<'de>,
+{
+    let timestamp = String::deserialize(deserializer)?;
+    chrono::DateTime::parse_from_rfc3339(&timestamp)
+        .map(|date| date.to_utc())
+        .map_err(serde::de::Error::custom)
+}
@@ -0,0 +1,136 @@
+use crate::error;
+use crate::error::Error;
+use crate::mongo::{GlyphMongo, AUTH_DB, SIGNING_KEY_COLL};
+use crate::util::UuidString;
+use base64::Engine;
+use bson::doc;
+use chrono::{DateTime, TimeDelta, Utc};
+use hmac::{Hmac, Mac};
+use jwt::Store;
+use mongodb::options::FindOptions;
+use mongodb::Collection;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::BTreeMap;
+use std::ops::Sub;
+use uuid::Uuid;
+
+/// How long a retired key is still accepted for verification. This matches the longest default
+/// token lifetime so rotating never invalidates a token that was issued before the rotation.
+const RETIRED_KEY_LIFETIME: i64 = 28800;
+
+/// An HMAC secret used to sign JWTs, stored in Mongo so tokens survive restarts.
+#[derive(Serialize, Deserialize)]
+pub struct SigningKey {
+    kid: String,
+    /// Base64 encoded secret.
+    secret: String,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    created: DateTime<Utc>,
+    /// When the key stopped being used to sign new tokens, [None] for the active key.
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime_optional", default)]
+    retired: Option<DateTime<Utc>>,
+}
+
+impl SigningKey {
+    fn generate() -> Self {
+        let mut secret = [0u8; 64];
+        rand::thread_rng().fill_bytes(&mut secret);
+        Self {
+            kid: Uuid::new_v4().to_stripped_string(),
+            secret: base64::engine::general_purpose::STANDARD.encode(secret),
+            created: Utc::now(),
+            retired: None,
+        }
+    }
+
+    fn hmac(&self) -> error::Result<Hmac<Sha256>> {
+        let secret = base64::engine::general_purpose::STANDARD.decode(&self.secret)
+            .map_err(|_| Error::MalformedSigningKey(self.kid.clone()))?;
+        Ok(Hmac::new_from_slice(&secret)?)
+    }
+}
+
+/// Every key that tokens can be verified with, keyed by key ID, along with the one new tokens are
+/// signed with.
+pub struct SigningKeys {
+    pub(crate) active_kid: String,
+    pub(crate) keys: BTreeMap<String, Hmac<Sha256>>,
+    /// When each retired key stops being accepted, keyed by key ID.
+    pub(crate) expires_at: BTreeMap<String, DateTime<Utc>>,
+}
+
+impl SigningKeys {
+    pub fn active(&self) -> (&str, &Hmac<Sha256>) {
+        let key = self.keys.get(&self.active_kid).expect("The active key is always loaded");
+        (&self.active_kid, key)
+    }
+}
+
+impl Store for SigningKeys {
+    type Algorithm = Hmac<Sha256>;
+
+    /// Returns the key with the supplied ID, unless it was retired long enough ago to have expired.
+    /// Expired keys are only deleted on the next load, so they have to be skipped here too.
+    fn get(&self, key_id: &str) -> Option<&Hmac<Sha256>> {
+        if self.expires_at.get(key_id).is_some_and(|expires_at| *expires_at <= Utc::now()) {
+            return None;
+        }
+        self.keys.get(key_id)
+    }
+}
+
+async fn key_collection(mongo: &GlyphMongo) -> Collection<SigningKey> {
+    mongo.collection::<SigningKey>(AUTH_DB, SIGNING_KEY_COLL).await
+}
+
+/// Loads every signing key that hasn't expired, generating and storing the first one if there are
+/// none yet.
+pub async fn load_signing_keys(mongo: &GlyphMongo) -> error::Result<SigningKeys> {
+    let collection = key_collection(mongo).await;
+    let cutoff = Utc::now().sub(TimeDelta::seconds(RETIRED_KEY_LIFETIME));
+    collection.delete_many(doc! { "retired": { "$lt": bson::DateTime::from_chrono(cutoff) } }).await?;
+
+    let options = FindOptions::builder().sort(doc! { "created": -1 }).build();
+    let mut cursor = collection.find(doc! {}).with_options(options).await?;
+    let mut active_kid = None;
+    let mut keys = BTreeMap::new();
+    let mut expires_at = BTreeMap::new();
+    while cursor.advance().await? {
+        let key = cursor.deserialize_current()?;
+        if key.retired.is_none() && active_kid.is_none() {
+            active_kid = Some(key.kid.clone());
+        }
+        if let Some(retired) = key.retired {
+            expires_at.insert(key.kid.clone(), retired + TimeDelta::seconds(RETIRED_KEY_LIFETIME));
+        }
+        keys.insert(key.kid.clone(), key.hmac()?);
+    }
+
+    let active_kid = match active_kid {
+        Some(val) => val,
+        None => {
+            let key = SigningKey::generate();
+            collection.insert_one(&key).await?;
+            keys.insert(key.kid.clone(), key.hmac()?);
+            key.kid
+        }
+    };
+
+    Ok(SigningKeys { active_kid, keys, expires_at })
+}
+
+/// Stores a freshly generated key, retires every other active key, then returns the reloaded keys.
+/// Retired keys are still accepted for verification until they expire. The new key is stored
+/// first so there is always an active key, even if retiring the old ones fails.
+pub async fn rotate_signing_key(mongo: &GlyphMongo) -> error::Result<SigningKeys> {
+    let collection = key_collection(mongo).await;
+    let key = SigningKey::generate();
+    collection.insert_one(&key).await?;
+    collection.update_many(
+        doc! { "retired": null, "kid": { "$ne": &key.kid } },
+        doc! { "$set": { "retired": bson::DateTime::from_chrono(Utc::now()) } },
+    ).await?;
+    load_signing_keys(mongo).await
+}
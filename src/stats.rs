@@ -0,0 +1,247 @@
+use crate::athena::matches::PlayerResult;
+use crate::error;
+use crate::mongo::{GlyphMongo, STATS_COLL, STATS_DB};
+use bson::doc;
+use chrono::{DateTime, Utc};
+use mongodb::options::{IndexOptions, UpdateOptions};
+use mongodb::IndexModel;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+
+/// The input type stats are reported under. The client only shows stats for the input it is
+/// using, and there is no way to tell from a match result, so everything is reported as one.
+const STATS_INPUT: &str = "keyboardmouse";
+
+/// The season new match results count towards, from `SEASON`. Defaults to 1.
+pub fn current_season() -> u32 {
+    std::env::var("SEASON").ok()
+        .and_then(|season| season.parse().ok())
+        .unwrap_or(1)
+}
+
+/// A player's totals in one playlist in one season, kept up to date by [record_result].
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PlaylistStats {
+    pub(crate) account_id: String,
+    pub(crate) season: u32,
+    /// The playlist name in lowercase, like `playlist_defaultsolo`.
+    pub(crate) playlist: String,
+    pub(crate) matches_played: i64,
+    pub(crate) wins: i64,
+    pub(crate) top10: i64,
+    pub(crate) top25: i64,
+    pub(crate) kills: i64,
+    pub(crate) minutes_played: i64,
+    pub(crate) score: i64,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub(crate) last_modified: DateTime<Utc>,
+}
+
+/// A stat leaderboards can be ranked by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LeaderboardStat {
+    Wins,
+    Kills,
+    MatchesPlayed,
+    Score,
+}
+
+impl LeaderboardStat {
+    /// The name used in requests and responses.
+    pub fn name(&self) -> &'static str {
+        match self {
+            LeaderboardStat::Wins => "wins",
+            LeaderboardStat::Kills => "kills",
+            LeaderboardStat::MatchesPlayed => "matchesplayed",
+            LeaderboardStat::Score => "score",
+        }
+    }
+
+    fn field(&self) -> &'static str {
+        match self {
+            LeaderboardStat::Wins => "wins",
+            LeaderboardStat::Kills => "kills",
+            LeaderboardStat::MatchesPlayed => "matches_played",
+            LeaderboardStat::Score => "score",
+        }
+    }
+
+    pub fn value(&self, stats: &PlaylistStats) -> i64 {
+        match self {
+            LeaderboardStat::Wins => stats.wins,
+            LeaderboardStat::Kills => stats.kills,
+            LeaderboardStat::MatchesPlayed => stats.matches_played,
+            LeaderboardStat::Score => stats.score,
+        }
+    }
+}
+
+/// Totals across any number of [PlaylistStats], see [summarize].
+#[derive(Default)]
+pub struct StatsSummary {
+    pub(crate) matches_played: i64,
+    pub(crate) wins: i64,
+    pub(crate) top10: i64,
+    pub(crate) kills: i64,
+    pub(crate) minutes_played: i64,
+}
+
+impl StatsSummary {
+    /// Kills per death, where every match that wasn't won counts as a death.
+    pub fn kd(&self) -> f64 {
+        let deaths = (self.matches_played - self.wins).max(1);
+        self.kills as f64 / deaths as f64
+    }
+
+    /// The percentage of matches that were won.
+    pub fn win_rate(&self) -> f64 {
+        if self.matches_played == 0 {
+            return 0.0;
+        }
+        self.wins as f64 * 100.0 / self.matches_played as f64
+    }
+}
+
+pub fn summarize(stats: &[PlaylistStats]) -> StatsSummary {
+    stats.iter().fold(StatsSummary::default(), |mut summary, stats| {
+        summary.matches_played += stats.matches_played;
+        summary.wins += stats.wins;
+        summary.top10 += stats.top10;
+        summary.kills += stats.kills;
+        summary.minutes_played += stats.minutes_played;
+        summary
+    })
+}
+
+/// Flattens stats into the `br_<stat>_<input>_m0_<playlist>` keys statsv2 responses use,
+/// summing the same playlist across seasons.
+pub fn to_statsv2(stats: &[PlaylistStats]) -> Map<String, Value> {
+    let mut flattened = Map::new();
+    let mut add = |name: &str, playlist: &str, value: i64| {
+        let key = format!("br_{}_{}_m0_{}", name, STATS_INPUT, playlist);
+        let total = flattened.get(&key).and_then(Value::as_i64).unwrap_or(0);
+        flattened.insert(key, json!(total + value));
+    };
+
+    for stats in stats {
+        add("matchesplayed", &stats.playlist, stats.matches_played);
+        add("placetop1", &stats.playlist, stats.wins);
+        add("placetop10", &stats.playlist, stats.top10);
+        add("placetop25", &stats.playlist, stats.top25);
+        add("kills", &stats.playlist, stats.kills);
+        add("minutesplayed", &stats.playlist, stats.minutes_played);
+        add("score", &stats.playlist, stats.score);
+    }
+    for stats in stats {
+        let key = format!("br_lastmodified_{}_m0_{}", STATS_INPUT, stats.playlist);
+        let last_modified = stats.last_modified.timestamp();
+        if flattened.get(&key).and_then(Value::as_i64).is_none_or(|existing| existing < last_modified) {
+            flattened.insert(key, json!(last_modified));
+        }
+    }
+    flattened
+}
+
+pub async fn create_indexes(mongo: &GlyphMongo) -> error::Result<()> {
+    let stats_collection = mongo.collection::<PlaylistStats>(STATS_DB, STATS_COLL).await;
+    stats_collection.create_indexes([
+        IndexModel::builder()
+            .keys(doc! { "account_id": 1, "season": 1, "playlist": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build(),
+        IndexModel::builder().keys(doc! { "season": 1, "playlist": 1 }).build(),
+    ]).await?;
+    Ok(())
+}
+
+/// Adds a match result to the player's stats for its playlist in the current season.
+pub async fn record_result(mongo: &GlyphMongo, account_id: &str, result: &PlayerResult, score: i64) -> error::Result<()> {
+    let stats_collection = mongo.collection::<PlaylistStats>(STATS_DB, STATS_COLL).await;
+    let filter = doc! {
+        "account_id": account_id,
+        "season": current_season(),
+        "playlist": result.playlist.to_lowercase(),
+    };
+    let update = doc! {
+        "$inc": {
+            "matches_played": 1_i64,
+            "wins": (result.placement == 1) as i64,
+            "top10": (result.placement <= 10) as i64,
+            "top25": (result.placement <= 25) as i64,
+            "kills": result.eliminations as i64,
+            "minutes_played": (result.time_alive / 60) as i64,
+            "score": score,
+        },
+        "$set": { "last_modified": bson::DateTime::from_chrono(Utc::now()) },
+    };
+    stats_collection.update_one(filter, update)
+        .with_options(UpdateOptions::builder().upsert(true).build())
+        .await?;
+    Ok(())
+}
+
+/// Returns every playlist the player has stats in, optionally limited to one season.
+pub async fn get_stats(mongo: &GlyphMongo, account_id: &str, season: Option<u32>) -> error::Result<Vec<PlaylistStats>> {
+    let stats_collection = mongo.collection::<PlaylistStats>(STATS_DB, STATS_COLL).await;
+    let mut filter = doc! { "account_id": account_id };
+    if let Some(season) = season {
+        filter.insert("season", season);
+    }
+
+    let mut cursor = stats_collection.find(filter).await?;
+    let mut stats = vec![];
+    while cursor.advance().await? {
+        stats.push(cursor.deserialize_current()?);
+    }
+    Ok(stats)
+}
+
+/// Like [get_stats] for every player at once, in a single query. Every player is in the returned
+/// map, with no stats if they haven't played.
+pub async fn get_stats_bulk(mongo: &GlyphMongo, account_ids: &[String]) -> error::Result<HashMap<String, Vec<PlaylistStats>>> {
+    let stats_collection = mongo.collection::<PlaylistStats>(STATS_DB, STATS_COLL).await;
+    let mut cursor = stats_collection.find(doc! { "account_id": { "$in": account_ids } }).await?;
+    let mut stats = account_ids.iter()
+        .map(|account_id| (account_id.clone(), vec![]))
+        .collect::<HashMap<_, _>>();
+    while cursor.advance().await? {
+        let entry: PlaylistStats = cursor.deserialize_current()?;
+        stats.entry(entry.account_id.clone()).or_default().push(entry);
+    }
+    Ok(stats)
+}
+
+/// One page of a leaderboard.
+pub struct LeaderboardPage {
+    /// Ranked from best to worst.
+    pub(crate) entries: Vec<PlaylistStats>,
+    pub(crate) total_entries: u64,
+}
+
+/// Ranks everyone that played the playlist in the season by the supplied stat, skipping the first
+/// `offset` entries.
+pub async fn get_leaderboard(
+    mongo: &GlyphMongo,
+    season: u32,
+    playlist: &str,
+    stat: LeaderboardStat,
+    offset: u64,
+    page_size: u64,
+) -> error::Result<LeaderboardPage> {
+    let stats_collection = mongo.collection::<PlaylistStats>(STATS_DB, STATS_COLL).await;
+    let filter = doc! { "season": season, "playlist": playlist.to_lowercase() };
+    let total_entries = stats_collection.count_documents(filter.clone()).await?;
+
+    let mut cursor = stats_collection.find(filter)
+        .sort(doc! { stat.field(): -1, "last_modified": 1 })
+        .skip(offset)
+        .limit(page_size as i64)
+        .await?;
+    let mut entries = vec![];
+    while cursor.advance().await? {
+        entries.push(cursor.deserialize_current()?);
+    }
+    Ok(LeaderboardPage { entries, total_entries })
+}
@@ -1,13 +1,34 @@
 use crate::error;
 use crate::error::Error;
-use crate::mongo::{GlyphMongo, USERS_COLL, USER_DB};
-use bson::doc;
-use chrono::{DateTime, Utc};
-use mongodb::Collection;
+use crate::mongo::{duplicate_key_index, is_duplicate_key, GlyphMongo, USERS_COLL, USER_DB};
+use bson::{doc, Document};
+use chrono::{DateTime, TimeDelta, Utc};
+use std::ops::{Add, Sub};
+use mongodb::options::{Collation, CollationStrength, IndexOptions, ReturnDocument};
+use mongodb::{Collection, IndexModel};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-#[derive(Serialize, Deserialize)]
+/// How long a user has to wait between display name changes.
+pub const DISPLAY_NAME_COOLDOWN_DAYS: i64 = 14;
+pub const MIN_DISPLAY_NAME_LEN: usize = 3;
+pub const MAX_DISPLAY_NAME_LEN: usize = 16;
+
+/// Names nobody can register, compared without case.
+const RESERVED_NAMES: [&str; 10] = [
+    "admin",
+    "administrator",
+    "epic",
+    "epicgames",
+    "fortnite",
+    "glyph",
+    "moderator",
+    "server",
+    "support",
+    "system",
+];
+
+#[derive(Serialize, Deserialize, Clone)]
 pub enum Platform {
     WeGame,
     EpicPCKorea,
@@ -23,14 +44,38 @@ pub enum Platform {
     Shared,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct DisplayNameHistory {
-    display_name: String,
+    pub(crate) display_name: String,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub(crate) changed_at: DateTime<Utc>,
+}
+
+/// A single ban or suspension. Records are never removed, lifting a ban early fills in
+/// `lifted_at` and `lifted_by` instead.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct BanRecord {
+    pub(crate) reason: String,
+    /// The Discord ID of the moderator that issued the ban.
+    pub(crate) issued_by: u64,
     #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
-    changed_at: DateTime<Utc>,
+    pub(crate) issued_at: DateTime<Utc>,
+    /// When a suspension ends, or [None] for a permanent ban.
+    #[serde(default, with = "bson::serde_helpers::chrono_datetime_as_bson_datetime_optional")]
+    pub(crate) expires_at: Option<DateTime<Utc>>,
+    #[serde(default, with = "bson::serde_helpers::chrono_datetime_as_bson_datetime_optional")]
+    pub(crate) lifted_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub(crate) lifted_by: Option<u64>,
 }
 
-#[derive(Serialize, Deserialize)]
+impl BanRecord {
+    pub fn is_active(&self) -> bool {
+        self.lifted_at.is_none() && self.expires_at.is_none_or(|expires_at| expires_at > Utc::now())
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct User {
     pub(crate) account_id: uuid::Uuid,
     pub(crate) display_name: String,
@@ -42,6 +87,71 @@ pub struct User {
     #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
     pub(crate) created: DateTime<Utc>,
     pub(crate) name_history: Vec<DisplayNameHistory>,
+    #[serde(default)]
+    pub(crate) ban_history: Vec<BanRecord>,
+}
+
+/// Display names are unique without regard to case, so every query on them uses this collation.
+fn display_name_collation() -> Collation {
+    Collation::builder()
+        .locale("en")
+        .strength(CollationStrength::Secondary)
+        .build()
+}
+
+/// Creates the unique indexes that back one account per Discord user and unique display names.
+pub async fn create_indexes(mongo: &GlyphMongo) -> error::Result<()> {
+    let user_collection = mongo.collection::<User>(USER_DB, USERS_COLL).await;
+    let unique = |collation: Option<Collation>| IndexOptions::builder()
+        .unique(true)
+        .collation(collation)
+        .build();
+
+    user_collection.create_indexes([
+        IndexModel::builder().keys(doc! { "account_id": 1 }).options(unique(None)).build(),
+        IndexModel::builder().keys(doc! { "discord_id": 1 }).options(unique(None)).build(),
+        IndexModel::builder()
+            .keys(doc! { "display_name": 1 })
+            .options(unique(Some(display_name_collation())))
+            .build(),
+    ]).await?;
+    Ok(())
+}
+
+/// Checks a display name against the same rules Epic uses: 3 to 16 characters made of letters,
+/// digits, spaces, `-`, `_` and `.`, without leading, trailing or repeated spaces.
+pub fn validate_display_name(display_name: &str) -> error::Result<()> {
+    let invalid = |reason: &str| Err(Error::InvalidDisplayName(reason.to_string()));
+    let len = display_name.chars().count();
+
+    if !(MIN_DISPLAY_NAME_LEN..=MAX_DISPLAY_NAME_LEN).contains(&len) {
+        return invalid(&format!("must be between {} and {} characters long", MIN_DISPLAY_NAME_LEN, MAX_DISPLAY_NAME_LEN));
+    }
+    if !display_name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, ' ' | '-' | '_' | '.')) {
+        return invalid("can only contain letters, numbers, spaces, dashes, underscores and periods");
+    }
+    if display_name.starts_with(' ') || display_name.ends_with(' ') || display_name.contains("  ") {
+        return invalid("can't start or end with a space or contain more than one space in a row");
+    }
+    if RESERVED_NAMES.iter().any(|reserved| reserved.eq_ignore_ascii_case(display_name)) {
+        return invalid("is reserved");
+    }
+    Ok(())
+}
+
+impl User {
+    /// When the user is next allowed to change their display name, or [None] if they can now.
+    pub fn next_display_name_change(&self) -> Option<DateTime<Utc>> {
+        let last_change = self.name_history.iter().map(|entry| entry.changed_at).max()?;
+        let next_change = last_change.add(TimeDelta::days(DISPLAY_NAME_COOLDOWN_DAYS));
+        (next_change > Utc::now()).then_some(next_change)
+    }
+
+    /// Returns the ban that currently applies to the user, if any. [User::banned] is only a hint
+    /// for queries since suspensions run out without it being cleared.
+    pub fn active_ban(&self) -> Option<&BanRecord> {
+        self.ban_history.iter().rev().find(|record| record.is_active())
+    }
 }
 
 async fn new_unused_uuid(coll: &Collection<User>) -> error::Result<Uuid> {
@@ -57,9 +167,19 @@ async fn new_unused_uuid(coll: &Collection<User>) -> error::Result<Uuid> {
     }
 }
 
+/// Creates an account for the supplied Discord user after checking that the display name is
+/// valid and unused and that the Discord user doesn't already have an account.
 pub async fn create_user(mongo: &GlyphMongo, discord_id: u64, display_name: String) -> error::Result<User> {
+    validate_display_name(&display_name)?;
     let user_collection = mongo.collection::<User>(USER_DB, USERS_COLL).await;
 
+    if get_user_by_discord_id(mongo, discord_id).await?.is_some() {
+        return Err(Error::DiscordAccountExists);
+    }
+    if get_user_by_display_name(mongo, &display_name).await?.is_some() {
+        return Err(Error::DisplayNameTaken(display_name));
+    }
+
     let uuid = new_unused_uuid(&user_collection).await?;
     let now = Utc::now();
     let user = User {
@@ -71,10 +191,17 @@ pub async fn create_user(mongo: &GlyphMongo, discord_id: u64, display_name: Stri
         platform: Platform::EpicPc,
         created: now,
         name_history: vec![],
+        ban_history: vec![],
     };
 
-    user_collection.insert_one(&user).await?;
-    Ok(user)
+    match user_collection.insert_one(&user).await {
+        Ok(_) => Ok(user),
+        Err(e) => match duplicate_key_index(&e) {
+            Some("display_name_1") => Err(Error::DisplayNameTaken(user.display_name)),
+            Some("discord_id_1") => Err(Error::DiscordAccountExists),
+            _ => Err(Error::MongoError(e)),
+        },
+    }
 }
 
 pub async fn get_user(mongo: &GlyphMongo, account_id: &Uuid) -> error::Result<Option<User>> {
@@ -82,3 +209,159 @@ pub async fn get_user(mongo: &GlyphMongo, account_id: &Uuid) -> error::Result<Op
     let found = user_collection.find_one(doc! { "account_id": account_id.to_string() }).await?;
     Ok(found)
 }
+
+/// Finds every user with one of the supplied account IDs. Accounts that don't exist are left out.
+pub async fn get_users(mongo: &GlyphMongo, account_ids: &[Uuid]) -> error::Result<Vec<User>> {
+    let user_collection = mongo.collection::<User>(USER_DB, USERS_COLL).await;
+    let account_ids = account_ids.iter().map(|id| id.to_string()).collect::<Vec<_>>();
+    let mut cursor = user_collection.find(doc! { "account_id": { "$in": account_ids } }).await?;
+    let mut found = vec![];
+    while cursor.advance().await? {
+        found.push(cursor.deserialize_current()?);
+    }
+    Ok(found)
+}
+
+pub async fn get_user_by_discord_id(mongo: &GlyphMongo, discord_id: u64) -> error::Result<Option<User>> {
+    let user_collection = mongo.collection::<User>(USER_DB, USERS_COLL).await;
+    let found = user_collection.find_one(doc! { "discord_id": discord_id as i64 }).await?;
+    Ok(found)
+}
+
+/// Finds a user by display name, ignoring case.
+pub async fn get_user_by_display_name(mongo: &GlyphMongo, display_name: &str) -> error::Result<Option<User>> {
+    let user_collection = mongo.collection::<User>(USER_DB, USERS_COLL).await;
+    let found = user_collection.find_one(doc! { "display_name": display_name })
+        .collation(display_name_collation())
+        .await?;
+    Ok(found)
+}
+
+/// Changes the display name of the supplied user and records the old one in their name history,
+/// returning the updated user. Changes are limited to one every [DISPLAY_NAME_COOLDOWN_DAYS], and
+/// the caller is responsible for killing tokens that still carry the old name. Changing to the
+/// current name does nothing and returns the user unchanged.
+pub async fn change_display_name(mongo: &GlyphMongo, user: &User, display_name: String) -> error::Result<User> {
+    validate_display_name(&display_name)?;
+    if display_name == user.display_name {
+        return Ok(user.clone());
+    }
+    if let Some(next_change) = user.next_display_name_change() {
+        return Err(Error::DisplayNameCooldown(next_change));
+    }
+    if let Some(existing) = get_user_by_display_name(mongo, &display_name).await? {
+        // Changing the case of your own name is allowed.
+        if existing.account_id != user.account_id {
+            return Err(Error::DisplayNameTaken(display_name));
+        }
+    }
+
+    let now = Utc::now();
+    let history = DisplayNameHistory {
+        display_name: user.display_name.clone(),
+        changed_at: now,
+    };
+    let history = bson::to_bson(&history).map_err(|e| Error::MongoError(e.into()))?;
+    let cooldown_start = now.sub(TimeDelta::days(DISPLAY_NAME_COOLDOWN_DAYS));
+    let user_collection = mongo.collection::<User>(USER_DB, USERS_COLL).await;
+    // The cooldown is part of the filter so that concurrent changes can't both get through.
+    let updated = user_collection.find_one_and_update(
+        doc! {
+            "account_id": user.account_id.to_string(),
+            "name_history.changed_at": { "$not": { "$gt": bson::DateTime::from_chrono(cooldown_start) } },
+        },
+        doc! {
+            "$set": { "display_name": &display_name },
+            "$push": { "name_history": history },
+        },
+    ).return_document(ReturnDocument::After).await;
+
+    match updated {
+        Ok(Some(val)) => Ok(val),
+        Ok(None) => match get_user(mongo, &user.account_id).await?.and_then(|user| user.next_display_name_change()) {
+            Some(next_change) => Err(Error::DisplayNameCooldown(next_change)),
+            None => Err(Error::UserNotFound),
+        },
+        Err(e) if is_duplicate_key(&e) => Err(Error::DisplayNameTaken(display_name)),
+        Err(e) => Err(Error::MongoError(e)),
+    }
+}
+
+/// Matches the ban records that [BanRecord::is_active] would accept, with every field prefixed by
+/// `prefix` so it can be used in `$elemMatch` or as an array filter.
+fn active_ban_filter(prefix: &str, now: DateTime<Utc>) -> Document {
+    doc! {
+        format!("{}lifted_at", prefix): null,
+        "$or": [
+            { format!("{}expires_at", prefix): null },
+            { format!("{}expires_at", prefix): { "$gt": bson::DateTime::from_chrono(now) } },
+        ],
+    }
+}
+
+/// Bans the supplied user until `expires_at`, or forever if it is [None], and returns the updated
+/// user. The caller is responsible for killing the user's tokens.
+pub async fn ban_user(
+    mongo: &GlyphMongo,
+    user: &User,
+    issued_by: u64,
+    reason: String,
+    expires_at: Option<DateTime<Utc>>,
+) -> error::Result<User> {
+    if user.active_ban().is_some() {
+        return Err(Error::AlreadyBanned);
+    }
+
+    let now = Utc::now();
+    let record = BanRecord {
+        reason,
+        issued_by,
+        issued_at: now,
+        expires_at,
+        lifted_at: None,
+        lifted_by: None,
+    };
+    let record = bson::to_bson(&record).map_err(|e| Error::MongoError(e.into()))?;
+    let user_collection = mongo.collection::<User>(USER_DB, USERS_COLL).await;
+    // Checking for an active ban in the filter stops two concurrent bans from both being added.
+    let updated = user_collection.find_one_and_update(
+        doc! {
+            "account_id": user.account_id.to_string(),
+            "ban_history": { "$not": { "$elemMatch": active_ban_filter("", now) } },
+        },
+        doc! {
+            "$set": { "banned": true },
+            "$push": { "ban_history": record },
+        },
+    ).return_document(ReturnDocument::After).await?;
+
+    match updated {
+        Some(val) => Ok(val),
+        None if get_user(mongo, &user.account_id).await?.is_some() => Err(Error::AlreadyBanned),
+        None => Err(Error::UserNotFound),
+    }
+}
+
+/// Lifts every active ban on the supplied user and returns the updated user.
+pub async fn unban_user(mongo: &GlyphMongo, user: &User, lifted_by: u64) -> error::Result<User> {
+    if user.active_ban().is_none() {
+        return Err(Error::NotBanned);
+    }
+
+    let now = Utc::now();
+    let user_collection = mongo.collection::<User>(USER_DB, USERS_COLL).await;
+    let updated = user_collection.find_one_and_update(
+        doc! { "account_id": user.account_id.to_string() },
+        doc! {
+            "$set": {
+                "banned": false,
+                "ban_history.$[active].lifted_at": bson::DateTime::from_chrono(now),
+                "ban_history.$[active].lifted_by": lifted_by as i64,
+            },
+        },
+    )
+        .array_filters([active_ban_filter("active.", now)])
+        .return_document(ReturnDocument::After)
+        .await?;
+    updated.ok_or(Error::UserNotFound)
+}
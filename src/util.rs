@@ -19,17 +19,24 @@ fn invalid_auth<T>(_: T) -> error::Error {
     error::Error::InvalidAuthorizationHeader
 }
 
-pub fn extract_client_id(headers: &HeaderMap) -> error::Result<String> {
-    let auth_str = headers.get("Authorization").unwrap().to_str().map_err(invalid_auth)?;
-    let decoded = base64::engine::general_purpose::STANDARD.decode(auth_str).map_err(invalid_auth)?;
-    let client_id = std::str::from_utf8(&*decoded).map_err(invalid_auth)?;
-    let split = client_id.split(':');
+/// Returns the client ID and secret from a basic `Authorization` header.
+pub fn extract_client_credentials(headers: &HeaderMap) -> error::Result<(String, String)> {
+    let auth_str = headers.get("Authorization")
+        .ok_or(error::Error::InvalidAuthorizationHeader)?
+        .to_str()
+        .map_err(invalid_auth)?;
+    // The scheme is optional since some launchers send the bare credentials.
+    let encoded = match auth_str.trim().split_once(' ') {
+        Some((scheme, encoded)) if scheme.eq_ignore_ascii_case("basic") => encoded,
+        Some(_) => return Err(error::Error::InvalidAuthorizationHeader),
+        None => auth_str,
+    };
 
-    let coll = split.collect::<Vec<&str>>();
-    if coll.len() == 2 {
-        Ok(coll[0].to_string())
-    } else {
-        Err(error::Error::InvalidAuthorizationHeader)
+    let decoded = base64::engine::general_purpose::STANDARD.decode(encoded.trim()).map_err(invalid_auth)?;
+    let credentials = std::str::from_utf8(&decoded).map_err(invalid_auth)?;
+    match credentials.split_once(':') {
+        Some((client_id, client_secret)) => Ok((client_id.to_string(), client_secret.to_string())),
+        None => Err(error::Error::InvalidAuthorizationHeader),
     }
 }
 
@@ -0,0 +1,132 @@
+use quick_xml::escape::escape;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::fmt::Display;
+
+/// A parsed XML element. Each WebSocket frame carries exactly one stanza, so the whole tree is
+/// kept in memory instead of streaming it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Element {
+    /// The qualified name, including any prefix like `stream:`.
+    name: String,
+    attrs: Vec<(String, String)>,
+    children: Vec<Element>,
+    text: String,
+}
+
+impl Element {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            attrs: vec![],
+            children: vec![],
+            text: String::new(),
+        }
+    }
+
+    /// Sets an attribute, replacing it if it is already set.
+    pub fn attr(mut self, name: &str, value: &str) -> Self {
+        self.set_attr(name, value);
+        self
+    }
+
+    pub fn child(mut self, child: Element) -> Self {
+        self.children.push(child);
+        self
+    }
+
+    pub fn text(mut self, text: &str) -> Self {
+        self.text = text.to_string();
+        self
+    }
+
+    pub fn set_attr(&mut self, name: &str, value: &str) {
+        match self.attrs.iter_mut().find(|(key, _)| key == name) {
+            Some((_, existing)) => *existing = value.to_string(),
+            None => self.attrs.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    pub fn get_attr(&self, name: &str) -> Option<&str> {
+        self.attrs.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+    }
+
+    /// The name without its prefix, so `stream:features` is `features`.
+    pub fn local_name(&self) -> &str {
+        self.name.rsplit(':').next().unwrap_or(&self.name)
+    }
+
+    /// Returns the first child with the supplied local name.
+    pub fn find(&self, local_name: &str) -> Option<&Element> {
+        self.children.iter().find(|child| child.local_name() == local_name)
+    }
+
+    pub fn text_content(&self) -> &str {
+        &self.text
+    }
+
+    /// Parses a single element, returning [None] if the XML is malformed or incomplete.
+    pub fn parse(xml: &str) -> Option<Element> {
+        let mut reader = Reader::from_str(xml);
+        let mut stack: Vec<Element> = vec![];
+        loop {
+            match reader.read_event().ok()? {
+                Event::Start(start) => stack.push(Self::from_start(&start)?),
+                Event::Empty(start) => {
+                    let element = Self::from_start(&start)?;
+                    match stack.last_mut() {
+                        Some(parent) => parent.children.push(element),
+                        None => return Some(element),
+                    }
+                }
+                Event::End(_) => {
+                    let element = stack.pop()?;
+                    match stack.last_mut() {
+                        Some(parent) => parent.children.push(element),
+                        None => return Some(element),
+                    }
+                }
+                Event::Text(text) => {
+                    if let Some(current) = stack.last_mut() {
+                        current.text.push_str(&text.unescape().ok()?);
+                    }
+                }
+                Event::CData(data) => {
+                    if let Some(current) = stack.last_mut() {
+                        current.text.push_str(&String::from_utf8_lossy(&data));
+                    }
+                }
+                Event::Eof => return None,
+                _ => {}
+            }
+        }
+    }
+
+    fn from_start(start: &BytesStart) -> Option<Element> {
+        let mut element = Element::new(&String::from_utf8_lossy(start.name().as_ref()));
+        for attr in start.attributes() {
+            let attr = attr.ok()?;
+            let value = attr.unescape_value().ok()?;
+            element.attrs.push((String::from_utf8_lossy(attr.key.as_ref()).to_string(), value.to_string()));
+        }
+        Some(element)
+    }
+}
+
+impl Display for Element {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<{}", self.name)?;
+        for (name, value) in &self.attrs {
+            write!(f, " {}=\"{}\"", name, escape(value))?;
+        }
+        if self.children.is_empty() && self.text.is_empty() {
+            return write!(f, "/>");
+        }
+
+        write!(f, ">{}", escape(&self.text))?;
+        for child in &self.children {
+            write!(f, "{}", child)?;
+        }
+        write!(f, "</{}>", self.name)
+    }
+}
@@ -0,0 +1,158 @@
+use crate::xmpp::element::Element;
+use chrono::{SecondsFormat, Utc};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::RwLock;
+use tokio::sync::mpsc::UnboundedSender;
+
+pub const XMPP_DOMAIN: &str = "prod.ol.epicgames.com";
+/// Server-pushed notifications come from this JID, the client ignores their body otherwise.
+pub const ADMIN_JID: &str = "xmpp-admin@prod.ol.epicgames.com";
+
+pub fn bare_jid(account_id: &str) -> String {
+    format!("{}@{}", account_id, XMPP_DOMAIN)
+}
+
+pub fn full_jid(account_id: &str, resource: &str) -> String {
+    format!("{}/{}", bare_jid(account_id), resource)
+}
+
+/// Splits a JID into the account ID and resource, or returns [None] if it doesn't belong to
+/// [XMPP_DOMAIN].
+pub fn parse_jid(jid: &str) -> Option<(&str, Option<&str>)> {
+    let (bare, resource) = match jid.split_once('/') {
+        Some((bare, resource)) => (bare, Some(resource)),
+        None => (jid, None),
+    };
+    let (account_id, domain) = bare.split_once('@')?;
+    (domain == XMPP_DOMAIN).then_some((account_id, resource))
+}
+
+/// What a session is asked to write to its socket.
+pub enum Outgoing {
+    Stanza(String),
+    /// Closes the stream, see [XmppHub::close].
+    Close,
+}
+
+/// A bound session, see [XmppHub::register].
+struct XmppClient {
+    tx: UnboundedSender<Outgoing>,
+    /// The access token the session signed in with.
+    token: String,
+    /// The last available presence the client sent, [None] until it sends one.
+    presence: Option<Element>,
+}
+
+/// Every connected XMPP session, keyed by account ID and then resource. Anything that needs to
+/// reach a player in real time goes through here.
+#[derive(Default)]
+pub struct XmppHub {
+    clients: RwLock<HashMap<String, HashMap<String, XmppClient>>>,
+}
+
+impl XmppHub {
+    /// Adds a session that stanzas can be delivered to, replacing any older session for the same
+    /// resource.
+    pub fn register(&self, account_id: &str, resource: &str, token: &str, tx: UnboundedSender<Outgoing>) {
+        let mut clients = self.clients.write().unwrap();
+        clients.entry(account_id.to_string())
+            .or_default()
+            .insert(resource.to_string(), XmppClient { tx, token: token.to_string(), presence: None });
+    }
+
+    /// Removes a session, but only if `tx` is still the one registered for its resource. A client
+    /// that reconnects with the same resource replaces the old session, which must not remove the
+    /// new one when it closes. Returns the presence the removed session had.
+    pub fn unregister(&self, account_id: &str, resource: &str, tx: &UnboundedSender<Outgoing>) -> Option<Element> {
+        let mut clients = self.clients.write().unwrap();
+        let resources = clients.get_mut(account_id)?;
+        if !resources.get(resource).is_some_and(|client| client.tx.same_channel(tx)) {
+            return None;
+        }
+
+        let removed = resources.remove(resource)?;
+        if resources.is_empty() {
+            clients.remove(account_id);
+        }
+        removed.presence
+    }
+
+    /// Stores the presence of a session, or clears it if [None]. Returns the presence it had
+    /// before.
+    pub fn set_presence(&self, account_id: &str, resource: &str, presence: Option<Element>) -> Option<Element> {
+        let mut clients = self.clients.write().unwrap();
+        let client = clients.get_mut(account_id)?.get_mut(resource)?;
+        std::mem::replace(&mut client.presence, presence)
+    }
+
+    /// Returns the resource and access token of every session of the account.
+    pub fn sessions(&self, account_id: &str) -> Vec<(String, String)> {
+        let clients = self.clients.read().unwrap();
+        let Some(resources) = clients.get(account_id) else {
+            return vec![];
+        };
+        resources.iter()
+            .map(|(resource, client)| (resource.clone(), client.token.clone()))
+            .collect()
+    }
+
+    /// Asks one session of the account, or all of them if `resource` is [None], to close its
+    /// stream. Each session unregisters itself and tells friends it went offline once it has.
+    /// Returns how many sessions were asked.
+    pub fn close(&self, account_id: &str, resource: Option<&str>) -> usize {
+        let clients = self.clients.read().unwrap();
+        let Some(resources) = clients.get(account_id) else {
+            return 0;
+        };
+        resources.iter()
+            .filter(|(client_resource, _)| resource.is_none_or(|resource| resource == *client_resource))
+            .filter(|(_, client)| client.tx.send(Outgoing::Close).is_ok())
+            .count()
+    }
+
+    /// Returns the full JID and last presence of every session of the account that has sent one.
+    pub fn presences(&self, account_id: &str) -> Vec<(String, Element)> {
+        let clients = self.clients.read().unwrap();
+        let Some(resources) = clients.get(account_id) else {
+            return vec![];
+        };
+        resources.iter()
+            .filter_map(|(resource, client)| Some((full_jid(account_id, resource), client.presence.clone()?)))
+            .collect()
+    }
+
+    /// Delivers a stanza to one session of the account, or to all of them if `resource` is
+    /// [None]. The `to` attribute is set to each session's full JID. Returns false if nothing
+    /// was delivered.
+    pub fn send(&self, account_id: &str, resource: Option<&str>, stanza: &Element) -> bool {
+        let clients = self.clients.read().unwrap();
+        let Some(resources) = clients.get(account_id) else {
+            return false;
+        };
+
+        let mut delivered = false;
+        for (client_resource, client) in resources {
+            if resource.is_some_and(|resource| resource != client_resource) {
+                continue;
+            }
+            let stanza = stanza.clone().attr("to", &full_jid(account_id, client_resource));
+            delivered |= client.tx.send(Outgoing::Stanza(stanza.to_string())).is_ok();
+        }
+        delivered
+    }
+
+    /// Pushes a notification to every session of the account. The client dispatches on the
+    /// `type` field of the payload, and a `timestamp` is added if it doesn't have one.
+    pub fn notify(&self, account_id: &str, mut payload: Value) -> bool {
+        if let Some(object) = payload.as_object_mut() {
+            object.entry("timestamp")
+                .or_insert_with(|| Value::String(Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)));
+        }
+        let message = Element::new("message")
+            .attr("xmlns", "jabber:client")
+            .attr("from", ADMIN_JID)
+            .child(Element::new("body").text(&payload.to_string()));
+        self.send(account_id, None, &message)
+    }
+}
@@ -0,0 +1,352 @@
+use crate::auth_manager::OAuthManager;
+use crate::friends;
+use crate::route::bearer::Bearer;
+use crate::util::UuidString;
+use crate::xmpp::element::Element;
+use crate::xmpp::hub::{bare_jid, full_jid, parse_jid, Outgoing, XMPP_DOMAIN};
+use crate::GlyphState;
+use axum::extract::ws::{Message, WebSocket};
+use base64::Engine;
+use log::{debug, error, warn};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+const FRAMING_NS: &str = "urn:ietf:params:xml:ns:xmpp-framing";
+const SASL_NS: &str = "urn:ietf:params:xml:ns:xmpp-sasl";
+const BIND_NS: &str = "urn:ietf:params:xml:ns:xmpp-bind";
+const SESSION_NS: &str = "urn:ietf:params:xml:ns:xmpp-session";
+const STREAM_NS: &str = "http://etherx.jabber.org/streams";
+const ROSTER_NS: &str = "jabber:iq:roster";
+
+/// The account a session signed in as. Stanzas can only be delivered to it once it has bound a
+/// resource.
+struct Client {
+    account_id: Uuid,
+    stripped_id: String,
+    /// The access token the session signed in with, see [close_revoked].
+    token: String,
+    resource: Option<String>,
+}
+
+/// One XMPP-over-WebSocket (RFC 7395) connection.
+struct Session {
+    state: Arc<GlyphState>,
+    tx: mpsc::UnboundedSender<Outgoing>,
+    client: Option<Client>,
+    closed: bool,
+}
+
+/// Runs the connection until either side closes it.
+pub async fn run(state: Arc<GlyphState>, mut socket: WebSocket) {
+    let (tx, mut rx) = mpsc::unbounded_channel::<Outgoing>();
+    let mut session = Session {
+        state,
+        tx,
+        client: None,
+        closed: false,
+    };
+
+    while !session.closed {
+        tokio::select! {
+            message = socket.recv() => {
+                let text = match message {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => continue,
+                };
+                match Element::parse(&text) {
+                    Some(stanza) => session.handle(stanza).await,
+                    None => debug!("Ignoring malformed XMPP frame: {}", text),
+                }
+            }
+            Some(outgoing) = rx.recv() => {
+                let text = match outgoing {
+                    Outgoing::Stanza(text) => text,
+                    Outgoing::Close => {
+                        session.send(Element::new("close").attr("xmlns", FRAMING_NS));
+                        session.closed = true;
+                        continue;
+                    }
+                };
+                if socket.send(Message::Text(text)).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+
+    // Anything queued before the close is still sent.
+    while let Ok(Outgoing::Stanza(text)) = rx.try_recv() {
+        if socket.send(Message::Text(text)).await.is_err() {
+            break;
+        }
+    }
+    session.disconnect().await;
+}
+
+impl Session {
+    fn send(&self, stanza: Element) {
+        // The receiver lives as long as the session, so this only fails while shutting down.
+        let _ = self.tx.send(Outgoing::Stanza(stanza.to_string()));
+    }
+
+    /// The full JID of the session, if it has signed in and bound a resource.
+    fn jid(&self) -> Option<String> {
+        let client = self.client.as_ref()?;
+        Some(full_jid(&client.stripped_id, client.resource.as_ref()?))
+    }
+
+    async fn handle(&mut self, stanza: Element) {
+        match stanza.local_name() {
+            "open" => self.open(),
+            "close" => {
+                self.send(Element::new("close").attr("xmlns", FRAMING_NS));
+                self.closed = true;
+            }
+            "auth" => self.auth(&stanza).await,
+            "iq" if self.client.is_some() => self.iq(&stanza).await,
+            "presence" if self.jid().is_some() => self.presence(stanza).await,
+            "message" if self.jid().is_some() => self.message(stanza).await,
+            name => debug!("Ignoring XMPP stanza {}", name),
+        }
+    }
+
+    /// Answers a stream open with the features for the current stage: SASL before signing in,
+    /// resource binding after.
+    fn open(&self) {
+        self.send(
+            Element::new("open")
+                .attr("xmlns", FRAMING_NS)
+                .attr("from", XMPP_DOMAIN)
+                .attr("id", &Uuid::new_v4().to_stripped_string())
+                .attr("version", "1.0")
+                .attr("xml:lang", "en"),
+        );
+
+        let features = Element::new("stream:features").attr("xmlns:stream", STREAM_NS);
+        let features = match self.client {
+            None => features.child(
+                Element::new("mechanisms")
+                    .attr("xmlns", SASL_NS)
+                    .child(Element::new("mechanism").text("PLAIN")),
+            ),
+            Some(_) => features
+                .child(Element::new("bind").attr("xmlns", BIND_NS))
+                .child(Element::new("session").attr("xmlns", SESSION_NS)),
+        };
+        self.send(features);
+    }
+
+    /// Signs in with SASL PLAIN, where the username is the account ID and the password is an
+    /// access token.
+    async fn auth(&mut self, stanza: &Element) {
+        if self.client.is_some() || stanza.get_attr("mechanism") != Some("PLAIN") {
+            return self.auth_failed();
+        }
+        let Some((account_id, token)) = parse_plain(stanza.text_content()) else {
+            return self.auth_failed();
+        };
+
+        let bearer = match Bearer::verify(&self.state, token.trim_start_matches("eg1~")).await {
+            Ok(val) => val,
+            Err(_) => return self.auth_failed(),
+        };
+        if bearer.user.account_id.to_stripped_string() != account_id {
+            return self.auth_failed();
+        }
+
+        self.client = Some(Client {
+            account_id: bearer.user.account_id,
+            stripped_id: account_id,
+            token: bearer.token.token,
+            resource: None,
+        });
+        self.send(Element::new("success").attr("xmlns", SASL_NS));
+    }
+
+    fn auth_failed(&mut self) {
+        self.send(
+            Element::new("failure")
+                .attr("xmlns", SASL_NS)
+                .child(Element::new("not-authorized")),
+        );
+        self.send(Element::new("close").attr("xmlns", FRAMING_NS));
+        self.closed = true;
+    }
+
+    async fn iq(&mut self, stanza: &Element) {
+        let id = stanza.get_attr("id").unwrap_or_default().to_string();
+        let result = Element::new("iq")
+            .attr("xmlns", "jabber:client")
+            .attr("type", "result")
+            .attr("id", &id)
+            .attr("from", XMPP_DOMAIN);
+
+        if let Some(bind) = stanza.find("bind") {
+            let result = self.bind(bind, result);
+            return self.send(result);
+        }
+        if stanza.find("query").is_some_and(|query| query.get_attr("xmlns") == Some(ROSTER_NS)) {
+            let result = self.roster(result).await;
+            return self.send(result);
+        }
+
+        // Sessions, pings and anything else the client asks for just get acknowledged.
+        self.send(result);
+    }
+
+    fn bind(&mut self, bind: &Element, result: Element) -> Element {
+        let client = self.client.as_mut().expect("only signed in sessions send iqs");
+        let resource = bind.find("resource")
+            .map(|resource| resource.text_content().to_string())
+            .filter(|resource| !resource.is_empty())
+            .unwrap_or_else(|| Uuid::new_v4().to_stripped_string());
+
+        if let Some(old) = client.resource.replace(resource.clone()) {
+            self.state.xmpp.unregister(&client.stripped_id, &old, &self.tx);
+        }
+        self.state.xmpp.register(&client.stripped_id, &resource, &client.token, self.tx.clone());
+
+        result.child(
+            Element::new("bind")
+                .attr("xmlns", BIND_NS)
+                .child(Element::new("jid").text(&full_jid(&client.stripped_id, &resource))),
+        )
+    }
+
+    async fn roster(&self, result: Element) -> Element {
+        let client = self.client.as_ref().expect("only signed in sessions send iqs");
+        let mut query = Element::new("query").attr("xmlns", ROSTER_NS);
+        for friend_id in self.friend_ids(client).await {
+            query = query.child(
+                Element::new("item")
+                    .attr("jid", &bare_jid(&friend_id))
+                    .attr("subscription", "both"),
+            );
+        }
+        result.child(query)
+    }
+
+    async fn friend_ids(&self, client: &Client) -> Vec<String> {
+        match friends::get_friends(&self.state.mongo, &client.account_id).await {
+            Ok(friendships) => friendships.iter()
+                .map(|friendship| friendship.other(&client.stripped_id).to_string())
+                .collect(),
+            Err(e) => {
+                error!("Failed to load friends for XMPP: {}", e);
+                vec![]
+            }
+        }
+    }
+
+    /// Stores the session's presence and sends it to every online friend. The first presence a
+    /// session sends also gets it the presence of every friend that is already online.
+    async fn presence(&mut self, mut stanza: Element) {
+        // Presence sent to a room or another player directly isn't supported.
+        if stanza.get_attr("to").is_some() {
+            return;
+        }
+
+        let jid = self.jid().expect("checked before handling presence");
+        let client = self.client.as_ref().expect("checked before handling presence");
+        let resource = client.resource.as_deref().expect("checked before handling presence");
+        let unavailable = stanza.get_attr("type") == Some("unavailable");
+
+        stanza.set_attr("xmlns", "jabber:client");
+        stanza.set_attr("from", &jid);
+        let stored = (!unavailable).then(|| stanza.clone());
+        let previous = self.state.xmpp.set_presence(&client.stripped_id, resource, stored);
+
+        let friend_ids = self.friend_ids(client).await;
+        for friend_id in &friend_ids {
+            self.state.xmpp.send(friend_id, None, &stanza);
+        }
+
+        if previous.is_none() && !unavailable {
+            for friend_id in &friend_ids {
+                for (_, presence) in self.state.xmpp.presences(friend_id) {
+                    self.send(presence.attr("to", &jid));
+                }
+            }
+        }
+    }
+
+    /// Forwards a direct message to another player, unless either of them blocked the other.
+    async fn message(&self, mut stanza: Element) {
+        let jid = self.jid().expect("checked before handling messages");
+        let client = self.client.as_ref().expect("checked before handling messages");
+        let Some((to_account, to_resource)) = stanza.get_attr("to").and_then(parse_jid) else {
+            return;
+        };
+        let (to_account, to_resource) = (to_account.to_string(), to_resource.map(str::to_string));
+        let Ok(to_uuid) = Uuid::parse_str(&to_account) else {
+            return;
+        };
+
+        match friends::is_blocked(&self.state.mongo, &client.account_id, &to_uuid).await {
+            Ok(false) => {}
+            Ok(true) => return,
+            Err(e) => {
+                error!("Failed to check blocks for an XMPP message: {}", e);
+                return;
+            }
+        }
+
+        stanza.set_attr("xmlns", "jabber:client");
+        stanza.set_attr("from", &jid);
+        if !self.state.xmpp.send(&to_account, to_resource.as_deref(), &stanza) {
+            debug!("Dropped an XMPP message to offline account {}", to_account);
+        }
+    }
+
+    /// Removes the session from the hub and tells friends it went offline.
+    async fn disconnect(&mut self) {
+        let Some(client) = self.client.take() else {
+            return;
+        };
+        let Some(resource) = client.resource.as_deref() else {
+            return;
+        };
+
+        let had_presence = self.state.xmpp.unregister(&client.stripped_id, resource, &self.tx).is_some();
+        if !had_presence {
+            return;
+        }
+
+        let unavailable = Element::new("presence")
+            .attr("xmlns", "jabber:client")
+            .attr("type", "unavailable")
+            .attr("from", &full_jid(&client.stripped_id, resource));
+        for friend_id in self.friend_ids(&client).await {
+            self.state.xmpp.send(&friend_id, None, &unavailable);
+        }
+    }
+}
+
+/// Closes every session of the account whose access token no longer exists. Called whenever
+/// tokens are killed, so signing out or being banned also ends presence and messaging.
+pub async fn close_revoked(state: &GlyphState, account_id: &str) {
+    for (resource, token) in state.xmpp.sessions(account_id) {
+        match OAuthManager::get_access_token(&state.mongo, &token).await {
+            Ok(Some(_)) => {}
+            Ok(None) => {
+                state.xmpp.close(account_id, Some(&resource));
+            }
+            Err(e) => error!("Failed to check the access token of an XMPP session: {}", e),
+        }
+    }
+}
+
+/// Decodes a SASL PLAIN message, `authzid \0 authcid \0 password`, into the account ID and token.
+fn parse_plain(encoded: &str) -> Option<(String, String)> {
+    let decoded = base64::engine::general_purpose::STANDARD.decode(encoded.trim()).ok()?;
+    let decoded = String::from_utf8(decoded).ok()?;
+    let mut parts = decoded.split('\0');
+    let (_authzid, account_id, token) = (parts.next()?, parts.next()?, parts.next()?);
+    if account_id.is_empty() || token.is_empty() {
+        warn!("Rejected an XMPP sign in with an empty account ID or token");
+        return None;
+    }
+    Some((account_id.to_string(), token.to_string()))
+}